@@ -0,0 +1,269 @@
+/*!
+Selection and health tracking for multiple OG backends, backed by proxy-wasm
+shared data so every HTTP context in the worker pool sees the same picture.
+
+`call_og_api` resolves `(cluster, host)` pairs from config into a flat list
+and asks [`select`] which index to dispatch to; the result of that dispatch
+is fed back through [`record_result`] so later calls (on this worker, and
+via cross-worker shared data, others) route around a backend that's
+currently failing. Writes go through the host's CAS token and retry a
+bounded number of times on conflict, mirroring `cache.rs`.
+*/
+
+use proxy_wasm::traits::Context;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+const HEALTH_KEY: &str = "og_backend_health";
+const MAX_CAS_RETRIES: u32 = 3;
+
+/// How `select` picks among multiple configured backends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum BalanceStrategy {
+    /// Stick to the first healthy backend; only move on once it's unhealthy.
+    #[default]
+    Failover,
+    /// Rotate across all healthy backends to spread load.
+    RoundRobin,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+struct BackendState {
+    consecutive_failures: u32,
+    /// 0 means healthy; otherwise the backend is skipped until this time.
+    unhealthy_until_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct HealthState {
+    backends: Vec<BackendState>,
+    round_robin_cursor: u64,
+}
+
+fn now_ms(ctx: &impl Context) -> u64 {
+    ctx.get_current_time()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn load(ctx: &impl Context, backend_count: usize) -> (HealthState, Option<u32>) {
+    let (bytes, cas) = ctx.get_shared_data(HEALTH_KEY);
+    let mut state: HealthState = bytes
+        .and_then(|b| serde_json::from_slice(&b).ok())
+        .unwrap_or_default();
+    state.backends.resize(backend_count, BackendState::default());
+    (state, cas)
+}
+
+/// Pick which of `backend_count` backends to dispatch to under `strategy`,
+/// skipping any still in their unhealthy cooldown window. `avoid`, when
+/// `Some`, is the backend this same request already tried and failed on -
+/// a per-request retry should move on to a *different* backend even when
+/// `unhealthy_after_failures` consecutive failures haven't yet accumulated
+/// to flag it unhealthy for everyone else's selections.
+pub(crate) fn select(ctx: &impl Context, backend_count: usize, strategy: BalanceStrategy, avoid: Option<usize>) -> usize {
+    if backend_count <= 1 {
+        return 0;
+    }
+
+    for _ in 0..MAX_CAS_RETRIES {
+        let (mut state, cas) = load(ctx, backend_count);
+        let now = now_ms(ctx);
+
+        // First pass skips `avoid` too; if that leaves nothing healthy,
+        // fall back to including it rather than returning a dead index.
+        let healthy_from = |start: usize, skip_avoid: bool| -> Option<usize> {
+            (0..backend_count)
+                .map(|offset| (start + offset) % backend_count)
+                .find(|&i| state.backends[i].unhealthy_until_ms <= now && !(skip_avoid && Some(i) == avoid))
+        };
+        let pick_from = |start: usize| healthy_from(start, true).or_else(|| healthy_from(start, false));
+
+        let chosen = match strategy {
+            BalanceStrategy::Failover => pick_from(0).unwrap_or(0),
+            BalanceStrategy::RoundRobin => pick_from(state.round_robin_cursor as usize).unwrap_or(0),
+        };
+
+        if strategy == BalanceStrategy::RoundRobin {
+            state.round_robin_cursor = (chosen as u64 + 1) % backend_count as u64;
+            let serialized = match serde_json::to_vec(&state) {
+                Ok(v) => v,
+                Err(_) => return chosen,
+            };
+            if ctx.set_shared_data(HEALTH_KEY, Some(&serialized), cas).is_ok() {
+                return chosen;
+            }
+            // CAS lost the race; retry so the cursor doesn't regress under concurrency.
+            continue;
+        }
+
+        return chosen;
+    }
+
+    0
+}
+
+/// Record whether dispatching to `index` succeeded, marking it unhealthy
+/// for `cooldown_ms` once `unhealthy_after_failures` consecutive failures
+/// have been seen.
+pub(crate) fn record_result(
+    ctx: &impl Context,
+    backend_count: usize,
+    index: usize,
+    success: bool,
+    unhealthy_after_failures: u32,
+    cooldown_ms: u64,
+) {
+    if index >= backend_count {
+        return;
+    }
+
+    for _ in 0..MAX_CAS_RETRIES {
+        let (mut state, cas) = load(ctx, backend_count);
+        let entry = &mut state.backends[index];
+        if success {
+            entry.consecutive_failures = 0;
+            entry.unhealthy_until_ms = 0;
+        } else {
+            entry.consecutive_failures += 1;
+            if entry.consecutive_failures >= unhealthy_after_failures {
+                entry.unhealthy_until_ms = now_ms(ctx) + cooldown_ms;
+            }
+        }
+
+        let serialized = match serde_json::to_vec(&state) {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+        if ctx.set_shared_data(HEALTH_KEY, Some(&serialized), cas).is_ok() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
+
+    /// Minimal in-memory stand-in for the proxy-wasm shared-data host calls -
+    /// see the identical mock in `cache.rs` for why this is needed instead of
+    /// `Context`'s hostcall-backed defaults.
+    struct MockCtx {
+        data: RefCell<HashMap<String, (Vec<u8>, u32)>>,
+        now_ms: Cell<u64>,
+    }
+
+    impl MockCtx {
+        fn new() -> Self {
+            MockCtx { data: RefCell::new(HashMap::new()), now_ms: Cell::new(1_000) }
+        }
+    }
+
+    impl Context for MockCtx {
+        fn get_current_time(&self) -> std::time::SystemTime {
+            std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_millis(self.now_ms.get())
+        }
+
+        fn get_shared_data(&self, key: &str) -> (Option<Vec<u8>>, Option<u32>) {
+            match self.data.borrow().get(key) {
+                Some((bytes, cas)) => (Some(bytes.clone()), Some(*cas)),
+                None => (None, None),
+            }
+        }
+
+        fn set_shared_data(&self, key: &str, value: Option<&[u8]>, cas: Option<u32>) -> Result<(), proxy_wasm::types::Status> {
+            let mut data = self.data.borrow_mut();
+            let current_cas = data.get(key).map(|(_, c)| *c);
+            if cas.is_some() && cas != current_cas {
+                return Err(proxy_wasm::types::Status::CasMismatch);
+            }
+            match value {
+                Some(bytes) => {
+                    data.insert(key.to_string(), (bytes.to_vec(), current_cas.unwrap_or(0) + 1));
+                }
+                None => {
+                    data.remove(key);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_select_single_backend_always_zero() {
+        let ctx = MockCtx::new();
+        assert_eq!(select(&ctx, 1, BalanceStrategy::Failover, None), 0);
+    }
+
+    #[test]
+    fn test_select_failover_sticks_to_backend_zero_until_unhealthy() {
+        let ctx = MockCtx::new();
+        assert_eq!(select(&ctx, 3, BalanceStrategy::Failover, None), 0);
+        assert_eq!(select(&ctx, 3, BalanceStrategy::Failover, None), 0);
+    }
+
+    #[test]
+    fn test_select_avoids_backend_that_just_failed() {
+        let ctx = MockCtx::new();
+        // Backend 0 hasn't accumulated enough consecutive failures to be
+        // marked unhealthy, but a same-request retry should still skip it.
+        let chosen = select(&ctx, 3, BalanceStrategy::Failover, Some(0));
+        assert_ne!(chosen, 0);
+    }
+
+    #[test]
+    fn test_select_falls_back_to_avoid_when_no_other_backend_is_healthy() {
+        let ctx = MockCtx::new();
+        // Mark backend 1 unhealthy, leaving only backend 0 - which is also
+        // the one this (hypothetical) request just failed on.
+        for _ in 0..3 {
+            record_result(&ctx, 2, 1, false, 3, 30_000);
+        }
+        assert_eq!(select(&ctx, 2, BalanceStrategy::Failover, Some(0)), 0);
+    }
+
+    #[test]
+    fn test_record_result_marks_unhealthy_after_threshold_and_select_routes_around_it() {
+        let ctx = MockCtx::new();
+        for _ in 0..3 {
+            record_result(&ctx, 2, 0, false, 3, 30_000);
+        }
+        assert_eq!(select(&ctx, 2, BalanceStrategy::Failover, None), 1);
+    }
+
+    #[test]
+    fn test_record_result_success_resets_consecutive_failures() {
+        let ctx = MockCtx::new();
+        record_result(&ctx, 2, 0, false, 3, 30_000);
+        record_result(&ctx, 2, 0, false, 3, 30_000);
+        record_result(&ctx, 2, 0, true, 3, 30_000);
+        record_result(&ctx, 2, 0, false, 3, 30_000);
+        // Only one consecutive failure since the reset, below the threshold.
+        assert_eq!(select(&ctx, 2, BalanceStrategy::Failover, None), 0);
+    }
+
+    #[test]
+    fn test_unhealthy_backend_recovers_after_cooldown_elapses() {
+        let ctx = MockCtx::new();
+        for _ in 0..3 {
+            record_result(&ctx, 2, 0, false, 3, 30_000);
+        }
+        assert_eq!(select(&ctx, 2, BalanceStrategy::Failover, None), 1);
+
+        ctx.now_ms.set(ctx.now_ms.get() + 30_001);
+        assert_eq!(select(&ctx, 2, BalanceStrategy::Failover, None), 0);
+    }
+
+    #[test]
+    fn test_round_robin_rotates_across_backends() {
+        let ctx = MockCtx::new();
+        let first = select(&ctx, 3, BalanceStrategy::RoundRobin, None);
+        let second = select(&ctx, 3, BalanceStrategy::RoundRobin, None);
+        let third = select(&ctx, 3, BalanceStrategy::RoundRobin, None);
+        assert_eq!(vec![first, second, third], vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,232 @@
+/*!
+Content-keyed detection result cache backed by proxy-wasm shared data.
+
+Every HTTP context in the worker pool can see the same shared-data keys, so
+repeated identical prompts across concurrent requests hit the cache instead
+of re-dispatching to the OG API. Shared data is visited concurrently, so all
+writes go through the host's compare-and-swap (CAS) token and retry a bounded
+number of times on conflict rather than overwriting a newer entry.
+*/
+
+use proxy_wasm::traits::Context;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// Shared-data key holding the insertion-ordered index used for eviction.
+const INDEX_KEY: &str = "og_cache_index";
+/// How many times to retry a CAS-guarded write before giving up.
+const MAX_CAS_RETRIES: u32 = 3;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedDecision {
+    inserted_at_ms: u64,
+    /// Raw JSON body returned by `/v1/gateway/process-input` or
+    /// `process-output`, replayed verbatim through the normal
+    /// `handle_input_response`/`handle_output_response` parsers on a hit.
+    body: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct IndexEntry {
+    key: String,
+    size: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct CacheIndex {
+    entries: Vec<IndexEntry>,
+}
+
+/// Stable cache key for `(application_id, detection path, canonicalized content)`.
+/// Uses FNV-1a since it's dependency-free and good enough for a shared-data
+/// lookup key (not a security boundary).
+pub(crate) fn cache_key(application_id: &str, path: &str, content: &str) -> String {
+    const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    let mut hash = FNV_OFFSET;
+    for byte in application_id
+        .bytes()
+        .chain(std::iter::once(0x1f))
+        .chain(path.bytes())
+        .chain(std::iter::once(0x1f))
+        .chain(content.bytes())
+    {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    format!("og_cache_{:016x}", hash)
+}
+
+fn now_ms(ctx: &impl Context) -> u64 {
+    ctx.get_current_time()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Look up `key`, returning the cached OG response body if present and
+/// still within `ttl_ms` of its insertion time.
+pub(crate) fn get(ctx: &impl Context, key: &str, ttl_ms: u64) -> Option<Vec<u8>> {
+    let (bytes, _cas) = ctx.get_shared_data(key);
+    let entry: CachedDecision = serde_json::from_slice(&bytes?).ok()?;
+    if now_ms(ctx).saturating_sub(entry.inserted_at_ms) > ttl_ms {
+        return None;
+    }
+    Some(entry.body.into_bytes())
+}
+
+/// Populate `key` with `body` and record it in the eviction index, evicting
+/// the oldest entries first if the total cached byte budget is exceeded.
+pub(crate) fn put(ctx: &impl Context, key: &str, body: &[u8], max_total_bytes: usize) {
+    let entry = CachedDecision {
+        inserted_at_ms: now_ms(ctx),
+        body: String::from_utf8_lossy(body).to_string(),
+    };
+    let serialized = match serde_json::to_vec(&entry) {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+
+    for _ in 0..MAX_CAS_RETRIES {
+        let (_, cas) = ctx.get_shared_data(key);
+        if ctx.set_shared_data(key, Some(&serialized), cas).is_ok() {
+            break;
+        }
+    }
+
+    update_index(ctx, key, serialized.len(), max_total_bytes);
+}
+
+fn update_index(ctx: &impl Context, new_key: &str, new_size: usize, max_total_bytes: usize) {
+    for _ in 0..MAX_CAS_RETRIES {
+        let (bytes, cas) = ctx.get_shared_data(INDEX_KEY);
+        let mut index: CacheIndex = bytes
+            .and_then(|b| serde_json::from_slice(&b).ok())
+            .unwrap_or_default();
+
+        index.entries.retain(|e| e.key != new_key);
+        index.entries.push(IndexEntry {
+            key: new_key.to_string(),
+            size: new_size,
+        });
+
+        let mut total: usize = index.entries.iter().map(|e| e.size).sum();
+        while total > max_total_bytes && index.entries.len() > 1 {
+            let evicted = index.entries.remove(0);
+            total = total.saturating_sub(evicted.size);
+            let _ = ctx.set_shared_data(&evicted.key, None, None);
+        }
+
+        let serialized_index = match serde_json::to_vec(&index) {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+        if ctx.set_shared_data(INDEX_KEY, Some(&serialized_index), cas).is_ok() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
+
+    /// Minimal in-memory stand-in for the proxy-wasm shared-data host calls,
+    /// since `Context`'s default methods call into hostcalls that only exist
+    /// inside a wasm host. `Context`'s other methods keep their default
+    /// (unused-by-`cache.rs`) implementations.
+    struct MockCtx {
+        data: RefCell<HashMap<String, (Vec<u8>, u32)>>,
+        now_ms: Cell<u64>,
+    }
+
+    impl MockCtx {
+        fn new() -> Self {
+            MockCtx { data: RefCell::new(HashMap::new()), now_ms: Cell::new(1_000) }
+        }
+    }
+
+    impl Context for MockCtx {
+        fn get_current_time(&self) -> std::time::SystemTime {
+            std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_millis(self.now_ms.get())
+        }
+
+        fn get_shared_data(&self, key: &str) -> (Option<Vec<u8>>, Option<u32>) {
+            match self.data.borrow().get(key) {
+                Some((bytes, cas)) => (Some(bytes.clone()), Some(*cas)),
+                None => (None, None),
+            }
+        }
+
+        fn set_shared_data(&self, key: &str, value: Option<&[u8]>, cas: Option<u32>) -> Result<(), proxy_wasm::types::Status> {
+            let mut data = self.data.borrow_mut();
+            let current_cas = data.get(key).map(|(_, c)| *c);
+            if cas.is_some() && cas != current_cas {
+                return Err(proxy_wasm::types::Status::CasMismatch);
+            }
+            match value {
+                Some(bytes) => {
+                    data.insert(key.to_string(), (bytes.to_vec(), current_cas.unwrap_or(0) + 1));
+                }
+                None => {
+                    data.remove(key);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_cache_key_is_stable_and_scoped_by_all_inputs() {
+        let a = cache_key("app1", "input", "hello");
+        let b = cache_key("app1", "input", "hello");
+        assert_eq!(a, b);
+
+        assert_ne!(a, cache_key("app2", "input", "hello"));
+        assert_ne!(a, cache_key("app1", "output", "hello"));
+        assert_ne!(a, cache_key("app1", "input", "world"));
+    }
+
+    #[test]
+    fn test_put_then_get_round_trips_body() {
+        let ctx = MockCtx::new();
+        let key = cache_key("app1", "input", "hello");
+        put(&ctx, &key, b"{\"action\":\"pass\"}", 1_048_576);
+
+        assert_eq!(get(&ctx, &key, 60_000).unwrap(), b"{\"action\":\"pass\"}");
+    }
+
+    #[test]
+    fn test_get_returns_none_once_ttl_elapsed() {
+        let ctx = MockCtx::new();
+        let key = cache_key("app1", "input", "hello");
+        put(&ctx, &key, b"cached", 1_048_576);
+
+        ctx.now_ms.set(ctx.now_ms.get() + 61_000);
+        assert_eq!(get(&ctx, &key, 60_000), None);
+    }
+
+    #[test]
+    fn test_get_missing_key_returns_none() {
+        let ctx = MockCtx::new();
+        assert_eq!(get(&ctx, "og_cache_absent", 60_000), None);
+    }
+
+    #[test]
+    fn test_update_index_evicts_oldest_entry_over_budget() {
+        let ctx = MockCtx::new();
+        let key_a = cache_key("app1", "input", "aaa");
+        let key_b = cache_key("app1", "input", "bbb");
+
+        // Each entry serializes to more than a handful of bytes; cap the
+        // budget tight enough that inserting the second evicts the first.
+        put(&ctx, &key_a, b"aaa", 40);
+        put(&ctx, &key_b, b"bbb", 40);
+
+        assert!(get(&ctx, &key_a, 60_000).is_none());
+        assert_eq!(get(&ctx, &key_b, 60_000).unwrap(), b"bbb");
+    }
+}
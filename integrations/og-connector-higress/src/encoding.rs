@@ -0,0 +1,109 @@
+/*!
+Content-Encoding decode support for request/response bodies that need
+inspection (gzip, deflate, brotli, zstd).
+
+Detection only ever needs the plaintext JSON, so rather than re-compressing a
+rewritten body, the header callbacks strip `content-encoding` up front for any
+codec we can decode and let this module turn the buffered body back into
+plaintext before `parse_messages`/`extract_response_content` run.
+*/
+
+use std::io::Read;
+
+/// Decode a buffered body with `codec` (the lowercased `content-encoding`
+/// value). Returns `None` for a codec we don't recognize or a body that
+/// fails to decode, so the caller can log and fail open.
+pub(crate) fn decode(body: &[u8], codec: &str) -> Option<Vec<u8>> {
+    match codec {
+        "gzip" | "x-gzip" => {
+            let mut out = Vec::new();
+            flate2::read::GzDecoder::new(body).read_to_end(&mut out).ok()?;
+            Some(out)
+        }
+        "deflate" => {
+            let mut out = Vec::new();
+            flate2::read::ZlibDecoder::new(body).read_to_end(&mut out).ok()?;
+            Some(out)
+        }
+        "br" => {
+            let mut out = Vec::new();
+            brotli::Decompressor::new(body, 4096).read_to_end(&mut out).ok()?;
+            Some(out)
+        }
+        "zstd" => {
+            let mut out = Vec::new();
+            zstd::stream::read::Decoder::new(body).ok()?.read_to_end(&mut out).ok()?;
+            Some(out)
+        }
+        _ => None,
+    }
+}
+
+/// Whether `codec` (a lowercased `content-encoding` value) is one [`decode`]
+/// knows how to handle.
+pub(crate) fn is_supported(codec: &str) -> bool {
+    matches!(codec, "gzip" | "x-gzip" | "deflate" | "br" | "zstd")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const PLAINTEXT: &[u8] = br#"{"messages":[{"role":"user","content":"hello"}]}"#;
+
+    #[test]
+    fn test_decode_gzip_round_trip() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(PLAINTEXT).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert_eq!(decode(&compressed, "gzip").unwrap(), PLAINTEXT);
+        assert_eq!(decode(&compressed, "x-gzip").unwrap(), PLAINTEXT);
+    }
+
+    #[test]
+    fn test_decode_deflate_round_trip() {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(PLAINTEXT).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert_eq!(decode(&compressed, "deflate").unwrap(), PLAINTEXT);
+    }
+
+    #[test]
+    fn test_decode_brotli_round_trip() {
+        let mut compressed = Vec::new();
+        {
+            let mut writer = brotli::CompressorWriter::new(&mut compressed, 4096, 5, 22);
+            writer.write_all(PLAINTEXT).unwrap();
+        }
+
+        assert_eq!(decode(&compressed, "br").unwrap(), PLAINTEXT);
+    }
+
+    #[test]
+    fn test_decode_zstd_round_trip() {
+        let compressed = zstd::stream::encode_all(PLAINTEXT, 0).unwrap();
+        assert_eq!(decode(&compressed, "zstd").unwrap(), PLAINTEXT);
+    }
+
+    #[test]
+    fn test_decode_unknown_codec_returns_none() {
+        assert_eq!(decode(PLAINTEXT, "identity"), None);
+    }
+
+    #[test]
+    fn test_decode_malformed_body_fails_open_with_none() {
+        assert_eq!(decode(b"not actually gzip", "gzip"), None);
+    }
+
+    #[test]
+    fn test_is_supported_matches_decode_codecs() {
+        for codec in ["gzip", "x-gzip", "deflate", "br", "zstd"] {
+            assert!(is_supported(codec));
+        }
+        assert!(!is_supported("identity"));
+        assert!(!is_supported("compress"));
+    }
+}
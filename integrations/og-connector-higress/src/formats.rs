@@ -0,0 +1,687 @@
+/*!
+Upstream request/response format adapters.
+
+`OGConnector` was originally hardcoded to the OpenAI chat-completions JSON
+shape (`messages` / `choices[0].message.content`). These traits let the
+connector sit in front of other upstreams routed through Higress (Anthropic
+Messages, Gemini) without each call site needing to know the wire format -
+mirroring how multi-provider LLM clients register one typed backend per
+provider behind a common interface.
+*/
+
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Joins multiple extracted text segments (message content, tool-call
+/// arguments, ...) into the single string sent to OG, and splits OG's
+/// returned replacement back into the same segments. A control character
+/// that never appears in legitimate JSON string content, so the split is
+/// unambiguous.
+const SEGMENT_SEP: &str = "\u{1e}";
+
+/// Which upstream wire format `provider` in `OGConnectorConfig` selects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Provider {
+    #[default]
+    OpenAi,
+    Anthropic,
+    Gemini,
+}
+
+/// Extracts the conversation turns OG needs for input detection, and splices
+/// anonymized turns back into the original request body.
+pub(crate) trait RequestFormat {
+    /// Pull the list of conversation turns out of the raw request JSON, in
+    /// the provider's native per-turn shape (as sent to OG for detection).
+    fn extract_messages(&self, body: &Value) -> Option<Vec<Value>>;
+
+    /// Write anonymized/replacement turns back into the request body,
+    /// preserving everything else (model, temperature, system prompt, ...).
+    fn apply_messages(&self, body: &mut Value, messages: &[Value]);
+}
+
+/// Extracts the assistant's reply text for output detection, and splices an
+/// anonymized/restored replacement back into the response body.
+pub(crate) trait ResponseFormat {
+    /// Pull the completion text out of the raw response JSON.
+    fn extract_text(&self, body: &Value) -> Option<String>;
+
+    /// Write replacement completion text back into the response body.
+    fn apply_text(&self, body: &mut Value, text: &str);
+
+    /// Pull the incremental delta text out of one parsed SSE event's JSON
+    /// payload during streaming. Returns `None` for events that carry no
+    /// assistant-visible text (e.g. OpenAI's `[DONE]`, Anthropic's
+    /// `message_start`/`ping`/tool-use deltas).
+    fn extract_delta_text(&self, event: &Value) -> Option<String>;
+
+    /// Rewrite one streamed event's delta text in place, returning the same
+    /// event with every other field (`id`/`model`/`created`/`usage`, other
+    /// choices, tool-call deltas, ...) untouched. The inverse of
+    /// [`extract_delta_text`] - used to splice an anonymized/restored
+    /// replacement back into the original frame instead of emitting a
+    /// synthetic one.
+    ///
+    /// [`extract_delta_text`]: Self::extract_delta_text
+    fn render_delta_event(&self, event: &Value, text: &str) -> Value;
+}
+
+pub(crate) trait UpstreamFormat: RequestFormat + ResponseFormat {}
+impl<T: RequestFormat + ResponseFormat> UpstreamFormat for T {}
+
+pub(crate) fn adapter_for(provider: Provider) -> Box<dyn UpstreamFormat> {
+    match provider {
+        Provider::OpenAi => Box::new(OpenAiFormat),
+        Provider::Anthropic => Box::new(AnthropicFormat),
+        Provider::Gemini => Box::new(GeminiFormat),
+    }
+}
+
+// ============= OpenAI (default) =============
+
+struct OpenAiFormat;
+
+impl RequestFormat for OpenAiFormat {
+    fn extract_messages(&self, body: &Value) -> Option<Vec<Value>> {
+        body.get("messages")?.as_array().cloned()
+    }
+
+    fn apply_messages(&self, body: &mut Value, messages: &[Value]) {
+        body["messages"] = Value::Array(messages.to_vec());
+    }
+}
+
+impl ResponseFormat for OpenAiFormat {
+    /// Walks every `choices[]` entry (not just `[0]`, so `n>1` is covered)
+    /// and collects `message.content`, each `tool_calls[].function.arguments`,
+    /// and the legacy `function_call.arguments` in encounter order, joined
+    /// with [`SEGMENT_SEP`] into the single string OG detects on.
+    fn extract_text(&self, body: &Value) -> Option<String> {
+        let choices = body.get("choices")?.as_array()?;
+        let mut segments = Vec::new();
+        for choice in choices {
+            let Some(message) = choice.get("message") else { continue; };
+
+            if let Some(content) = message.get("content").and_then(|c| c.as_str()) {
+                segments.push(content.to_string());
+            }
+            if let Some(tool_calls) = message.get("tool_calls").and_then(|t| t.as_array()) {
+                for call in tool_calls {
+                    if let Some(args) = call.get("function").and_then(|f| f.get("arguments")).and_then(|a| a.as_str()) {
+                        segments.push(args.to_string());
+                    }
+                }
+            }
+            if let Some(args) = message.get("function_call").and_then(|f| f.get("arguments")).and_then(|a| a.as_str()) {
+                segments.push(args.to_string());
+            }
+        }
+
+        if segments.is_empty() { None } else { Some(segments.join(SEGMENT_SEP)) }
+    }
+
+    /// Splices [`extract_text`]'s joined segments back into the exact
+    /// positions they came from, replacing only the string leaves so
+    /// surrounding JSON structure (tool-call ids, types, ...) is preserved.
+    /// If OG returned a different segment count than was sent, the leftover
+    /// fields are left untouched rather than guessing at a mapping.
+    fn apply_text(&self, body: &mut Value, text: &str) {
+        let mut segments = text.split(SEGMENT_SEP);
+        let Some(choices) = body.get_mut("choices").and_then(|c| c.as_array_mut()) else { return; };
+
+        for choice in choices {
+            let Some(message) = choice.get_mut("message") else { continue; };
+
+            if message.get("content").and_then(|c| c.as_str()).is_some() {
+                if let Some(seg) = segments.next() {
+                    message["content"] = Value::String(seg.to_string());
+                }
+            }
+            if let Some(tool_calls) = message.get_mut("tool_calls").and_then(|t| t.as_array_mut()) {
+                for call in tool_calls {
+                    let has_args = call.get("function").and_then(|f| f.get("arguments")).and_then(|a| a.as_str()).is_some();
+                    if has_args {
+                        if let Some(seg) = segments.next() {
+                            if let Some(function) = call.get_mut("function") {
+                                function["arguments"] = Value::String(seg.to_string());
+                            }
+                        }
+                    }
+                }
+            }
+            if message.get("function_call").and_then(|f| f.get("arguments")).and_then(|a| a.as_str()).is_some() {
+                if let Some(seg) = segments.next() {
+                    if let Some(function_call) = message.get_mut("function_call") {
+                        function_call["arguments"] = Value::String(seg.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    /// OpenAI streaming chunks carry the same shape as the non-streaming
+    /// message, just with `delta` in place of `message`.
+    fn extract_delta_text(&self, event: &Value) -> Option<String> {
+        event.get("choices")?.get(0)?.get("delta")?.get("content")?.as_str().map(|s| s.to_string())
+    }
+
+    /// Replaces `choices[0].delta.content`, leaving `id`/`model`/`created`/
+    /// `system_fingerprint`/`usage` and any other `choices[]` entries (an
+    /// `n>1` stream only ever has its first choice's text inspected, so only
+    /// it is rewritten) exactly as the upstream sent them.
+    fn render_delta_event(&self, event: &Value, text: &str) -> Value {
+        let mut out = event.clone();
+        if let Some(delta) = out
+            .get_mut("choices")
+            .and_then(|c| c.as_array_mut())
+            .and_then(|choices| choices.get_mut(0))
+            .and_then(|choice| choice.get_mut("delta"))
+        {
+            delta["content"] = Value::String(text.to_string());
+        }
+        out
+    }
+}
+
+// ============= Anthropic Messages =============
+
+struct AnthropicFormat;
+
+impl RequestFormat for AnthropicFormat {
+    fn extract_messages(&self, body: &Value) -> Option<Vec<Value>> {
+        let mut turns = Vec::new();
+
+        // Anthropic keeps the system prompt outside `messages`; surface it
+        // as a synthetic leading turn so OG still sees the full context.
+        if let Some(system) = body.get("system") {
+            if let Some(text) = system.as_str() {
+                turns.push(serde_json::json!({ "role": "system", "content": text }));
+            }
+        }
+
+        for message in body.get("messages")?.as_array()? {
+            let role = message.get("role").and_then(|r| r.as_str()).unwrap_or("user");
+            turns.push(serde_json::json!({
+                "role": role,
+                "content": anthropic_content_text(message.get("content")),
+            }));
+        }
+        Some(turns)
+    }
+
+    /// Splices each turn's (possibly anonymized) text back into the
+    /// matching original `messages[]` entry instead of rebuilding it from
+    /// scratch, so non-text blocks (`image`, `tool_use`, `tool_result`, ...)
+    /// on turns OG never modified - or alongside a text block on a turn it
+    /// did - survive untouched. `messages` is in the same order
+    /// `extract_messages` produced it (an optional leading system turn,
+    /// then one entry per original `messages[]` turn), so it zips back
+    /// against the original array directly.
+    fn apply_messages(&self, body: &mut Value, messages: &[Value]) {
+        let mut turns = messages.iter();
+
+        if body.get("system").and_then(|s| s.as_str()).is_some() {
+            if let Some(system_turn) = turns.next() {
+                if let Some(text) = system_turn.get("content").and_then(|c| c.as_str()) {
+                    body["system"] = Value::String(text.to_string());
+                }
+            }
+        }
+
+        let Some(original_messages) = body.get_mut("messages").and_then(|m| m.as_array_mut()) else { return; };
+        for (original, turn) in original_messages.iter_mut().zip(turns) {
+            let Some(text) = turn.get("content").and_then(|c| c.as_str()) else { continue; };
+            match original.get_mut("content") {
+                Some(Value::String(s)) => *s = text.to_string(),
+                Some(Value::Array(blocks)) => splice_text_segments(blocks, text),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl ResponseFormat for AnthropicFormat {
+    /// Joins every `text`-type block's content with [`SEGMENT_SEP`], the same
+    /// way `OpenAiFormat::extract_text` joins multiple content-bearing
+    /// fields, so [`apply_text`] can splice each segment back into the block
+    /// it came from instead of guessing at a merge.
+    ///
+    /// [`apply_text`]: Self::apply_text
+    fn extract_text(&self, body: &Value) -> Option<String> {
+        let blocks = body.get("content")?.as_array()?;
+        let segments: Vec<&str> = blocks
+            .iter()
+            .filter(|b| b.get("type").and_then(|t| t.as_str()) == Some("text"))
+            .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+            .collect();
+        if segments.is_empty() { None } else { Some(segments.join(SEGMENT_SEP)) }
+    }
+
+    /// Splices [`extract_text`]'s joined segments back into each `text`-type
+    /// block in order, leaving other block types (`tool_use`, `image`, ...)
+    /// untouched. If OG returned a different segment count, leftover blocks
+    /// keep their original text rather than guessing at a mapping.
+    ///
+    /// [`extract_text`]: Self::extract_text
+    fn apply_text(&self, body: &mut Value, text: &str) {
+        let mut segments = text.split(SEGMENT_SEP);
+        let Some(blocks) = body.get_mut("content").and_then(|c| c.as_array_mut()) else { return; };
+
+        for block in blocks {
+            if block.get("type").and_then(|t| t.as_str()) != Some("text") {
+                continue;
+            }
+            if block.get("text").and_then(|t| t.as_str()).is_some() {
+                if let Some(seg) = segments.next() {
+                    block["text"] = Value::String(seg.to_string());
+                }
+            }
+        }
+    }
+
+    /// Anthropic streams one `content_block_delta` event per text increment,
+    /// with the text under `delta.text` (`delta.type == "text_delta"`).
+    /// Other event types (`message_start`, `content_block_start`, `ping`,
+    /// `message_delta`, `message_stop`, and tool-use `input_json_delta`)
+    /// carry no assistant-visible text.
+    fn extract_delta_text(&self, event: &Value) -> Option<String> {
+        if event.get("type").and_then(|t| t.as_str()) != Some("content_block_delta") {
+            return None;
+        }
+        let delta = event.get("delta")?;
+        if delta.get("type").and_then(|t| t.as_str()) != Some("text_delta") {
+            return None;
+        }
+        delta.get("text")?.as_str().map(|s| s.to_string())
+    }
+
+    /// Replaces `delta.text` on a `content_block_delta` event, leaving
+    /// `index` and `delta.type` untouched.
+    fn render_delta_event(&self, event: &Value, text: &str) -> Value {
+        let mut out = event.clone();
+        if let Some(delta) = out.get_mut("delta") {
+            delta["text"] = Value::String(text.to_string());
+        }
+        out
+    }
+}
+
+/// Anthropic message `content` is either a plain string or an array of
+/// typed blocks (`text`, `image`, `tool_use`, ...); OG only needs the text.
+/// Multiple text blocks are joined with [`SEGMENT_SEP`] rather than
+/// concatenated, so `apply_messages` can splice a replacement segment back
+/// into the block it came from instead of merging them into one.
+fn anthropic_content_text(content: Option<&Value>) -> String {
+    match content {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(blocks)) => blocks
+            .iter()
+            .filter(|b| b.get("type").and_then(|t| t.as_str()) == Some("text"))
+            .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join(SEGMENT_SEP),
+        _ => String::new(),
+    }
+}
+
+// ============= Gemini =============
+
+struct GeminiFormat;
+
+impl RequestFormat for GeminiFormat {
+    fn extract_messages(&self, body: &Value) -> Option<Vec<Value>> {
+        let contents = body.get("contents")?.as_array()?;
+        let turns = contents
+            .iter()
+            .map(|content| {
+                let role = content.get("role").and_then(|r| r.as_str()).unwrap_or("user");
+                serde_json::json!({ "role": role, "content": gemini_parts_text_segmented(content) })
+            })
+            .collect();
+        Some(turns)
+    }
+
+    /// Splices each turn's (possibly anonymized) text back into the
+    /// matching original `contents[]` entry's `parts[]`, the same treatment
+    /// `AnthropicFormat::apply_messages` gives `content[]` blocks, so
+    /// `functionCall`/`inlineData` parts survive untouched instead of being
+    /// dropped by a from-scratch rebuild.
+    fn apply_messages(&self, body: &mut Value, messages: &[Value]) {
+        let Some(contents) = body.get_mut("contents").and_then(|c| c.as_array_mut()) else { return; };
+        for (original, turn) in contents.iter_mut().zip(messages) {
+            let Some(text) = turn.get("content").and_then(|c| c.as_str()) else { continue; };
+            if let Some(parts) = original.get_mut("parts").and_then(|p| p.as_array_mut()) {
+                splice_text_segments(parts, text);
+            }
+        }
+    }
+}
+
+impl ResponseFormat for GeminiFormat {
+    /// Joins every part's `text` field with [`SEGMENT_SEP`] instead of
+    /// `gemini_parts_text`'s plain concatenation, so [`apply_text`] can
+    /// splice each segment back into the part it came from - a response with
+    /// a `functionCall` part alongside text parts keeps that part intact.
+    ///
+    /// [`apply_text`]: Self::apply_text
+    fn extract_text(&self, body: &Value) -> Option<String> {
+        let parts = body.get("candidates")?.get(0)?.get("content")?.get("parts")?.as_array()?;
+        let segments: Vec<&str> = parts.iter().filter_map(|p| p.get("text").and_then(|t| t.as_str())).collect();
+        if segments.is_empty() { None } else { Some(segments.join(SEGMENT_SEP)) }
+    }
+
+    /// Splices [`extract_text`]'s joined segments back into each part that
+    /// carries a `text` field, in order, leaving `functionCall`/`inlineData`
+    /// parts untouched. If OG returned a different segment count, leftover
+    /// parts keep their original text rather than guessing at a mapping.
+    ///
+    /// [`extract_text`]: Self::extract_text
+    fn apply_text(&self, body: &mut Value, text: &str) {
+        let mut segments = text.split(SEGMENT_SEP);
+        let Some(parts) = body
+            .get_mut("candidates")
+            .and_then(|c| c.as_array_mut())
+            .and_then(|candidates| candidates.get_mut(0))
+            .and_then(|candidate| candidate.get_mut("content"))
+            .and_then(|content| content.get_mut("parts"))
+            .and_then(|p| p.as_array_mut())
+        else { return; };
+
+        for part in parts {
+            if part.get("text").and_then(|t| t.as_str()).is_some() {
+                if let Some(seg) = segments.next() {
+                    part["text"] = Value::String(seg.to_string());
+                }
+            }
+        }
+    }
+
+    /// Gemini's streamed chunks are partial `GenerateContentResponse`
+    /// objects with the same `candidates[].content.parts[].text` shape as
+    /// the non-streaming response, so this mirrors `gemini_parts_text`.
+    fn extract_delta_text(&self, event: &Value) -> Option<String> {
+        let text = gemini_parts_text(event.get("candidates")?.get(0)?.get("content")?);
+        if text.is_empty() { None } else { Some(text) }
+    }
+
+    /// Collapses `candidates[0].content.parts` to a single rewritten text
+    /// part, the same merge `extract_delta_text` already performs when
+    /// reading multiple parts back out.
+    fn render_delta_event(&self, event: &Value, text: &str) -> Value {
+        let mut out = event.clone();
+        if let Some(content) = out
+            .get_mut("candidates")
+            .and_then(|c| c.as_array_mut())
+            .and_then(|candidates| candidates.get_mut(0))
+            .and_then(|candidate| candidate.get_mut("content"))
+        {
+            content["parts"] = serde_json::json!([{ "text": text }]);
+        }
+        out
+    }
+}
+
+/// Splits `text` on [`SEGMENT_SEP`] and writes each segment back into the
+/// next block/part carrying a `text` field, in order - the same splicing
+/// [`ResponseFormat::apply_text`] does for response content. Blocks with no
+/// `text` field at all (`tool_use`, `image`, `functionCall`, `inlineData`,
+/// ...) are left alone. If there are fewer segments than text-bearing
+/// blocks, the leftover blocks keep their original text rather than
+/// guessing at a mapping. Shared by `AnthropicFormat`/`GeminiFormat`'s
+/// `apply_messages`.
+fn splice_text_segments(blocks: &mut [Value], text: &str) {
+    let mut segments = text.split(SEGMENT_SEP);
+    for block in blocks.iter_mut() {
+        if block.get("text").and_then(|t| t.as_str()).is_some() {
+            if let Some(seg) = segments.next() {
+                block["text"] = Value::String(seg.to_string());
+            }
+        }
+    }
+}
+
+fn gemini_parts_text(content: &Value) -> String {
+    content
+        .get("parts")
+        .and_then(|p| p.as_array())
+        .map(|parts| {
+            parts
+                .iter()
+                .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+                .collect::<Vec<_>>()
+                .join("")
+        })
+        .unwrap_or_default()
+}
+
+/// Same extraction as [`gemini_parts_text`], but joins multiple text parts
+/// with [`SEGMENT_SEP`] instead of concatenating them, so
+/// `GeminiFormat::apply_messages` can splice a replacement segment back into
+/// the part it came from. Kept separate from `gemini_parts_text` (used by
+/// streaming delta extraction, where there's no per-part splice-back to do).
+fn gemini_parts_text_segmented(content: &Value) -> String {
+    content
+        .get("parts")
+        .and_then(|p| p.as_array())
+        .map(|parts| {
+            parts
+                .iter()
+                .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+                .collect::<Vec<_>>()
+                .join(SEGMENT_SEP)
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openai_extract_and_apply_text_preserves_tool_calls() {
+        let mut body = serde_json::json!({
+            "choices": [{
+                "message": {
+                    "content": "hello",
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": { "name": "lookup", "arguments": "{\"q\":\"x\"}" }
+                    }]
+                }
+            }]
+        });
+
+        let format = OpenAiFormat;
+        let text = format.extract_text(&body).unwrap();
+        assert_eq!(text, format!("hello{}{{\"q\":\"x\"}}", SEGMENT_SEP));
+
+        let replacement = format!("HELLO{}{{\"q\":\"y\"}}", SEGMENT_SEP);
+        format.apply_text(&mut body, &replacement);
+
+        assert_eq!(body["choices"][0]["message"]["content"], "HELLO");
+        assert_eq!(body["choices"][0]["message"]["tool_calls"][0]["function"]["arguments"], "{\"q\":\"y\"}");
+        // The tool call's id/type survive untouched.
+        assert_eq!(body["choices"][0]["message"]["tool_calls"][0]["id"], "call_1");
+    }
+
+    #[test]
+    fn test_anthropic_request_round_trip_surfaces_system_prompt() {
+        let body = serde_json::json!({
+            "system": "be terse",
+            "messages": [{ "role": "user", "content": "hi" }],
+        });
+        let format = AnthropicFormat;
+        let turns = format.extract_messages(&body).unwrap();
+        assert_eq!(turns.len(), 2);
+        assert_eq!(turns[0]["role"], "system");
+        assert_eq!(turns[0]["content"], "be terse");
+        assert_eq!(turns[1]["role"], "user");
+        assert_eq!(turns[1]["content"], "hi");
+    }
+
+    #[test]
+    fn test_anthropic_apply_text_preserves_non_text_blocks() {
+        let mut body = serde_json::json!({
+            "content": [
+                { "type": "text", "text": "hello" },
+                { "type": "tool_use", "id": "t1", "name": "lookup", "input": { "q": "x" } },
+                { "type": "text", "text": "world" },
+            ]
+        });
+        let format = AnthropicFormat;
+        let text = format.extract_text(&body).unwrap();
+        assert_eq!(text, format!("hello{}world", SEGMENT_SEP));
+
+        let replacement = format!("HELLO{}WORLD", SEGMENT_SEP);
+        format.apply_text(&mut body, &replacement);
+
+        assert_eq!(body["content"][0]["text"], "HELLO");
+        assert_eq!(body["content"][1]["type"], "tool_use");
+        assert_eq!(body["content"][1]["input"]["q"], "x");
+        assert_eq!(body["content"][2]["text"], "WORLD");
+    }
+
+    #[test]
+    fn test_anthropic_apply_messages_preserves_non_text_blocks() {
+        let mut body = serde_json::json!({
+            "system": "be terse",
+            "messages": [
+                { "role": "user", "content": [
+                    { "type": "text", "text": "hello" },
+                    { "type": "tool_use", "id": "t1", "name": "lookup", "input": { "q": "x" } },
+                ]},
+                { "role": "assistant", "content": "plain string turn" },
+            ],
+        });
+        let format = AnthropicFormat;
+        let turns = format.extract_messages(&body).unwrap();
+        let anonymized: Vec<Value> = turns
+            .iter()
+            .map(|t| serde_json::json!({ "role": t["role"], "content": format!("REDACTED-{}", t["content"].as_str().unwrap()) }))
+            .collect();
+
+        format.apply_messages(&mut body, &anonymized);
+
+        assert_eq!(body["system"], "REDACTED-be terse");
+        assert_eq!(body["messages"][0]["content"][0]["text"], "REDACTED-hello");
+        assert_eq!(body["messages"][0]["content"][1]["type"], "tool_use");
+        assert_eq!(body["messages"][0]["content"][1]["input"]["q"], "x");
+        assert_eq!(body["messages"][1]["content"], "REDACTED-plain string turn");
+    }
+
+    #[test]
+    fn test_anthropic_apply_messages_splices_multiple_text_blocks_independently() {
+        let mut body = serde_json::json!({
+            "messages": [
+                { "role": "user", "content": [
+                    { "type": "text", "text": "Part A" },
+                    { "type": "tool_use", "id": "t1", "name": "lookup", "input": { "q": "x" } },
+                    { "type": "text", "text": "Part B" },
+                ]},
+            ],
+        });
+        let format = AnthropicFormat;
+        let turns = format.extract_messages(&body).unwrap();
+        assert_eq!(turns[0]["content"], format!("Part A{}Part B", SEGMENT_SEP));
+
+        let replacement = format!("PART A{}PART B", SEGMENT_SEP);
+        let anonymized = vec![serde_json::json!({ "role": "user", "content": replacement })];
+        format.apply_messages(&mut body, &anonymized);
+
+        assert_eq!(body["messages"][0]["content"][0]["text"], "PART A");
+        assert_eq!(body["messages"][0]["content"][1]["type"], "tool_use");
+        assert_eq!(body["messages"][0]["content"][2]["text"], "PART B");
+    }
+
+    #[test]
+    fn test_gemini_apply_messages_preserves_function_call_parts() {
+        let mut body = serde_json::json!({
+            "contents": [{
+                "role": "user",
+                "parts": [
+                    { "text": "hello" },
+                    { "functionCall": { "name": "lookup", "args": { "q": "x" } } },
+                ],
+            }],
+        });
+        let format = GeminiFormat;
+        let turns = format.extract_messages(&body).unwrap();
+        let anonymized = vec![serde_json::json!({ "role": "user", "content": "REDACTED" })];
+        assert_eq!(turns[0]["content"], "hello");
+
+        format.apply_messages(&mut body, &anonymized);
+
+        assert_eq!(body["contents"][0]["parts"][0]["text"], "REDACTED");
+        assert_eq!(body["contents"][0]["parts"][1]["functionCall"]["name"], "lookup");
+    }
+
+    #[test]
+    fn test_gemini_apply_text_preserves_function_call_parts() {
+        let mut body = serde_json::json!({
+            "candidates": [{
+                "content": {
+                    "parts": [
+                        { "text": "hello" },
+                        { "functionCall": { "name": "lookup", "args": { "q": "x" } } },
+                    ]
+                }
+            }]
+        });
+        let format = GeminiFormat;
+        let text = format.extract_text(&body).unwrap();
+        assert_eq!(text, "hello");
+
+        format.apply_text(&mut body, "HELLO");
+
+        assert_eq!(body["candidates"][0]["content"]["parts"][0]["text"], "HELLO");
+        assert_eq!(body["candidates"][0]["content"]["parts"][1]["functionCall"]["name"], "lookup");
+    }
+
+    #[test]
+    fn test_openai_render_delta_event_preserves_other_fields() {
+        let event = serde_json::json!({
+            "id": "chatcmpl-1",
+            "model": "gpt-4o",
+            "choices": [
+                { "index": 0, "delta": { "content": "hi" }, "finish_reason": null },
+                { "index": 1, "delta": { "content": "yo" }, "finish_reason": null },
+            ],
+        });
+        let rendered = OpenAiFormat.render_delta_event(&event, "HI");
+        assert_eq!(rendered["id"], "chatcmpl-1");
+        assert_eq!(rendered["choices"][0]["delta"]["content"], "HI");
+        // Only the first choice (the one `extract_delta_text` reads) is rewritten.
+        assert_eq!(rendered["choices"][1]["delta"]["content"], "yo");
+    }
+
+    #[test]
+    fn test_anthropic_render_delta_event_preserves_index() {
+        let event = serde_json::json!({
+            "type": "content_block_delta",
+            "index": 2,
+            "delta": { "type": "text_delta", "text": "hi" },
+        });
+        let rendered = AnthropicFormat.render_delta_event(&event, "HI");
+        assert_eq!(rendered["index"], 2);
+        assert_eq!(rendered["delta"]["text"], "HI");
+    }
+
+    #[test]
+    fn test_gemini_render_delta_event_replaces_parts() {
+        let event = serde_json::json!({
+            "candidates": [{ "content": { "parts": [{ "text": "hi" }] } }]
+        });
+        let rendered = GeminiFormat.render_delta_event(&event, "HI");
+        assert_eq!(rendered["candidates"][0]["content"]["parts"][0]["text"], "HI");
+    }
+
+    #[test]
+    fn test_gemini_extract_text_none_when_no_text_parts() {
+        let body = serde_json::json!({
+            "candidates": [{ "content": { "parts": [{ "functionCall": { "name": "x" } }] } }]
+        });
+        assert_eq!(GeminiFormat.extract_text(&body), None);
+    }
+}
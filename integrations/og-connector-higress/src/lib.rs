@@ -4,8 +4,19 @@
 use proxy_wasm::traits::{Context, HttpContext, RootContext};
 use proxy_wasm::types::{Action, ContextType, DataAction, HeaderAction, LogLevel};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::time::Duration;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime};
+
+mod backends;
+mod cache;
+mod encoding;
+mod formats;
+mod metrics;
+mod sse;
+mod websocket;
+
+use backends::BalanceStrategy;
+use formats::Provider;
 
 /// Safely truncate a string at character boundary (not byte boundary)
 /// This prevents panic when slicing UTF-8 strings with multi-byte characters
@@ -18,6 +29,59 @@ fn safe_truncate(s: &str, max_chars: usize) -> String {
     }
 }
 
+/// Largest byte index `<= idx` that lies on a UTF-8 character boundary of
+/// `s`. Used to trim SSE window byte offsets without panicking on a
+/// multi-byte character straddling the cut point.
+fn floor_char_boundary(s: &str, idx: usize) -> usize {
+    let mut idx = idx.min(s.len());
+    while idx > 0 && !s.is_char_boundary(idx) {
+        idx -= 1;
+    }
+    idx
+}
+
+/// Smallest byte index `>= idx` that lies on a UTF-8 character boundary of
+/// `s`. Used when slicing into the head/tail halves `truncate_for_inspection`
+/// keeps of an oversized text.
+fn ceil_char_boundary(s: &str, idx: usize) -> usize {
+    let mut idx = idx.min(s.len());
+    while idx < s.len() && !s.is_char_boundary(idx) {
+        idx += 1;
+    }
+    idx
+}
+
+/// Marks the boundary between the previously-released overlap context and
+/// the new content in an SSE detection window sent to OG, so the returned
+/// replacement can be split at the same logical point even when
+/// anonymize/restore changes the text's byte length. Unlike `formats::
+/// SEGMENT_SEP` (a single control character, safe there because it only ever
+/// splits JSON-structural segments), this marker sits inside arbitrary
+/// model-streamed text, so it's a run of three rare control characters
+/// rather than one - cheap insurance against a single stray byte in the
+/// overlap text being mistaken for the real boundary.
+const SSE_OVERLAP_MARKER: &str = "\u{1d}\u{1e}\u{1f}";
+
+/// Bound a single message/response text sent to OG for detection to
+/// `max_inspect_bytes` (0 = unlimited). An oversized text is reduced to its
+/// head and tail halves - where a prompt-injection payload or PII is most
+/// likely to sit for one outsized turn - joined by a marker, and the second
+/// return value tells the caller to tag the OG request `truncated` so the
+/// verdict is known to have been made on a partial view.
+fn truncate_for_inspection(text: &str, max_inspect_bytes: usize) -> (String, bool) {
+    if max_inspect_bytes == 0 || text.len() <= max_inspect_bytes {
+        return (text.to_string(), false);
+    }
+    let half = max_inspect_bytes / 2;
+    let head_end = floor_char_boundary(text, half);
+    let tail_start = ceil_char_boundary(text, text.len().saturating_sub(half));
+    let mut out = String::with_capacity(max_inspect_bytes + 32);
+    out.push_str(&text[..head_end]);
+    out.push_str("...[truncated]...");
+    out.push_str(&text[tail_start..]);
+    (out, true)
+}
+
 proxy_wasm::main! {{
     // Set to Debug level for detailed K8s troubleshooting
     proxy_wasm::set_log_level(LogLevel::Debug);
@@ -46,6 +110,14 @@ struct RuleConfig {
     config: OGConnectorConfig,
 }
 
+/// One additional OG backend for load balancing/failover, alongside the
+/// primary `og_cluster`/`og_base_url`.
+#[derive(Debug, Clone, Deserialize, Default)]
+struct BackendConfig {
+    og_cluster: String,
+    og_base_url: String,
+}
+
 #[derive(Debug, Clone, Deserialize, Default)]
 struct OGConnectorConfig {
     #[serde(default)]
@@ -54,18 +126,139 @@ struct OGConnectorConfig {
     og_base_url: String,
     #[serde(default)]
     og_api_key: String,
+    /// Additional OG backends behind the same API key. When empty,
+    /// `og_cluster`/`og_base_url` is the only backend.
+    #[serde(default)]
+    backends: Vec<BackendConfig>,
+    /// How to pick among multiple backends.
+    #[serde(default)]
+    balance_strategy: BalanceStrategy,
+    /// Consecutive dispatch failures before a backend is marked unhealthy
+    /// (and skipped until `backend_cooldown_ms` has passed).
+    #[serde(default = "default_unhealthy_after_failures")]
+    unhealthy_after_failures: u32,
+    /// How long an unhealthy backend is skipped before being re-probed.
+    #[serde(default = "default_backend_cooldown_ms")]
+    backend_cooldown_ms: u64,
     #[serde(default)]
     application_id: String,
+    /// Upstream wire format to parse/rebuild request and response bodies as.
+    /// Defaults to OpenAI's `messages`/`choices[0].message.content` shape.
+    #[serde(default)]
+    provider: Provider,
+    /// Per-attempt deadline passed straight to `dispatch_http_call`; the
+    /// host delivers `on_http_call_response` with no `:status` header once
+    /// it expires, which `on_http_call_response` treats as a failure and
+    /// routes through `handle_og_api_failure`/`failure_mode` like any other
+    /// dispatch error - no separate timer is needed.
     #[serde(default = "default_timeout")]
     timeout_ms: u64,
     #[serde(default = "default_true")]
     enable_input_detection: bool,
     #[serde(default = "default_true")]
     enable_output_detection: bool,
+    /// How long a cached detection decision stays valid for an identical
+    /// `(application_id, path, content)` key. 0 disables the cache.
+    #[serde(default)]
+    cache_ttl_ms: u64,
+    /// Total bytes of cached decisions to keep in shared data before
+    /// evicting the oldest entries.
+    #[serde(default = "default_cache_max_bytes")]
+    cache_max_bytes: usize,
+    /// What to do once the OG API has failed (dispatch error, non-200, or
+    /// timeout) on every attempt, including retries. `on_detection_error` is
+    /// accepted as an alias, since that's the name some deployments already
+    /// use for this same fail-open/fail-closed switch.
+    #[serde(default, alias = "on_detection_error")]
+    failure_mode: FailureMode,
+    /// How many times to retry a failed OG API call before applying
+    /// `failure_mode`. 0 disables retries.
+    #[serde(default)]
+    max_retries: u32,
+    /// HTTP status for the local block response `failure_mode: fail_closed`
+    /// sends once retries are exhausted. 503 (unavailable) fits an outage;
+    /// some deployments prefer 403 (treat an unreachable detector as a
+    /// denied request) instead.
+    #[serde(default = "default_fail_closed_status")]
+    fail_closed_status_code: u32,
+    /// Bytes of newly-accumulated SSE delta content that trigger a windowed
+    /// `process-output` call instead of waiting for the stream to finish.
+    /// 0 disables windowing and falls back to a single end-of-stream check.
+    #[serde(default = "default_sse_window_bytes")]
+    sse_window_bytes: usize,
+    /// Trailing bytes of already-released SSE content carried into the next
+    /// window's detection call, so content split across a window boundary
+    /// is still seen together. Never re-emitted downstream - see
+    /// `OGConnector::dispatch_sse_window`.
+    #[serde(default = "default_sse_overlap_bytes")]
+    sse_overlap_bytes: usize,
+    /// Minimum `content-length` (as declared by the upstream, before
+    /// detection strips it) worth decoding a compressed response body for.
+    /// Responses below this are forwarded with their `content-encoding`
+    /// left intact and uninspected, since decoding a tiny body isn't worth
+    /// the codec overhead.
+    #[serde(default = "default_encoding_min_bytes")]
+    encoding_min_bytes: usize,
+    /// Largest request body `on_http_request_headers` will let through,
+    /// checked against the client's declared `content-length` when it sends
+    /// `Expect: 100-continue`, before anything is buffered. 0 disables the
+    /// check. Requests without `Expect: 100-continue` aren't checked against
+    /// this limit at all.
+    #[serde(default)]
+    max_request_body_bytes: usize,
+    /// Largest single message/response text forwarded to OG for inspection.
+    /// 0 disables the budget. A field over budget is reduced to its head and
+    /// tail halves by [`truncate_for_inspection`] and the OG request is
+    /// tagged `truncated`, rather than sending the whole thing - this bounds
+    /// per-call latency and OG-side payload size for large batch prompts.
+    /// With `block_on_oversized` set, a body whose declared `content-length`
+    /// already exceeds this is rejected in `on_http_request_headers`/
+    /// `on_http_response_headers` before anything is buffered, which is the
+    /// common case since most upstreams send `content-length`. A body with
+    /// no declared length (chunked transfer-encoding) can't be sized until
+    /// it's fully buffered, so it's still checked again, body already in
+    /// hand, at the point `get_*_body(0, body_size)` returns - fetching it
+    /// in fixed-size slices instead of that one call would need a streaming
+    /// JSON parser this connector doesn't have.
+    #[serde(default)]
+    max_inspect_bytes: usize,
+    /// When a request/response body exceeds `max_inspect_bytes`, reject it
+    /// locally instead of inspecting a truncated view. Off by default
+    /// (inspect-truncated-and-continue).
+    #[serde(default)]
+    block_on_oversized: bool,
 }
 
 fn default_timeout() -> u64 { 5000 }
 fn default_true() -> bool { true }
+fn default_cache_max_bytes() -> usize { 1_048_576 }
+fn default_unhealthy_after_failures() -> u32 { 3 }
+fn default_backend_cooldown_ms() -> u64 { 30_000 }
+fn default_sse_window_bytes() -> usize { 4096 }
+fn default_sse_overlap_bytes() -> usize { 200 }
+fn default_encoding_min_bytes() -> usize { 256 }
+fn default_fail_closed_status() -> u32 { 503 }
+
+impl OGConnectorConfig {
+    /// Flat `(cluster, base_url)` list: the primary backend followed by any
+    /// configured in `backends`.
+    fn backend_list(&self) -> Vec<(String, String)> {
+        std::iter::once((self.og_cluster.clone(), self.og_base_url.clone()))
+            .chain(self.backends.iter().map(|b| (b.og_cluster.clone(), b.og_base_url.clone())))
+            .collect()
+    }
+}
+
+/// What `OGConnector` does once `max_retries` OG API attempts have all failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum FailureMode {
+    /// Forward the original request/response untouched, as if OG were absent.
+    #[default]
+    FailOpen,
+    /// Terminate the request with a deterministic local block response.
+    FailClosed,
+}
 
 // ============= OG API Types =============
 
@@ -74,6 +267,16 @@ struct OGInputRequest {
     messages: Vec<serde_json::Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     application_id: Option<String>,
+    /// Set when one or more `messages[].content` fields were reduced to
+    /// their head/tail halves by `max_inspect_bytes` - see
+    /// `truncate_for_inspection`. Omitted entirely when false so this stays
+    /// invisible to OG deployments that predate the field.
+    #[serde(skip_serializing_if = "is_false")]
+    truncated: bool,
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
 }
 
 #[derive(Debug, Deserialize)]
@@ -130,6 +333,10 @@ struct OGOutputRequest {
     /// Input messages as context for output detection
     #[serde(skip_serializing_if = "Option::is_none")]
     messages: Option<Vec<serde_json::Value>>,
+    /// Set when `content` was reduced to its head/tail halves by
+    /// `max_inspect_bytes` - see `truncate_for_inspection`.
+    #[serde(skip_serializing_if = "is_false")]
+    truncated: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -164,6 +371,14 @@ enum ConnectorState {
     Initial,
     WaitingInputResponse,
     WaitingOutputResponse,
+    /// Input detection in flight for one reassembled WebSocket text message
+    /// (see `handle_ws_request_body`), as opposed to the whole buffered
+    /// request body in `WaitingInputResponse`.
+    WaitingWsInputResponse,
+    /// Output detection in flight for one reassembled WebSocket text message
+    /// (see `handle_ws_response_body`), the model -> client counterpart of
+    /// `WaitingWsInputResponse`.
+    WaitingWsOutputResponse,
     Done,
 }
 
@@ -171,11 +386,14 @@ enum ConnectorState {
 
 struct OGConnectorRoot {
     config: Option<OGConnectorConfig>,
+    /// Metric handles, registered once with the host in `on_configure` and
+    /// shared by every `OGConnector` HTTP context.
+    metrics: metrics::Metrics,
 }
 
 impl OGConnectorRoot {
     fn new() -> Self {
-        OGConnectorRoot { config: None }
+        OGConnectorRoot { config: None, metrics: metrics::Metrics::default() }
     }
 }
 
@@ -289,6 +507,7 @@ impl RootContext for OGConnectorRoot {
                     log::error!("[OG-CONFIG]   enable_output_detection={}", rule.config.enable_output_detection);
 
                     self.config = Some(rule.config.clone());
+                    self.metrics = metrics::Metrics::define();
                     log::error!("[OG-CONFIG] Step 5e: Config stored successfully!");
                     log::error!("[OG-CONFIG] ========== ON_CONFIGURE END (SUCCESS - HIGRESS FORMAT) ==========");
                     return true;
@@ -320,6 +539,7 @@ impl RootContext for OGConnectorRoot {
                 log::error!("[OG-CONFIG]   enable_output_detection={}", config.enable_output_detection);
 
                 self.config = Some(config);
+                self.metrics = metrics::Metrics::define();
                 log::error!("[OG-CONFIG] Step 6c: Config stored successfully!");
                 log::error!("[OG-CONFIG] ========== ON_CONFIGURE END (SUCCESS - DIRECT FORMAT) ==========");
                 return true;
@@ -348,6 +568,7 @@ impl RootContext for OGConnectorRoot {
         Some(Box::new(OGConnector {
             context_id,
             config: self.config.clone(),
+            metrics: self.metrics,
             state: ConnectorState::Initial,
             request_body: Vec::new(),
             response_body: Vec::new(),
@@ -359,6 +580,34 @@ impl RootContext for OGConnectorRoot {
             pending_proxy_response: None,
             response_sent: false,
             consumer_id: None,
+            is_sse_response: false,
+            response_content_encoding: String::new(),
+            request_content_encoding: String::new(),
+            sse_buffer: Vec::new(),
+            sse_content_accum: String::new(),
+            sse_pending_frames: Vec::new(),
+            pending_cache_key: None,
+            dispatch_started_at_ms: None,
+            pending_retry: None,
+            retry_count: 0,
+            active_backend_index: None,
+            sse_emitted_bytes: 0,
+            sse_window_overlap_len: 0,
+            sse_final_flush: false,
+            sse_window_end: 0,
+            sse_stream_ended: false,
+            is_websocket: false,
+            ws_req_buffer: Vec::new(),
+            ws_req_fragment: None,
+            ws_req_pending: VecDeque::new(),
+            ws_req_forward_buf: Vec::new(),
+            ws_req_text_pending: None,
+            ws_resp_buffer: Vec::new(),
+            ws_resp_fragment: None,
+            ws_resp_pending: VecDeque::new(),
+            ws_resp_forward_buf: Vec::new(),
+            ws_resp_text_pending: None,
+            ws_mask_counter: 0,
         }))
     }
 
@@ -388,6 +637,107 @@ struct OGConnector {
     response_sent: bool,
     /// Consumer ID from gateway (e.g., x-mse-consumer from Higress)
     consumer_id: Option<String>,
+    /// Whether the upstream response is `Content-Type: text/event-stream`
+    is_sse_response: bool,
+    /// Lowercased `content-encoding` of the upstream response, captured in
+    /// `on_http_response_headers` before the header is stripped. Empty means
+    /// identity or an unsupported codec we left untouched.
+    response_content_encoding: String,
+    /// Lowercased `content-encoding` of the client request, captured in
+    /// `on_http_request_headers` before the header is stripped. Empty means
+    /// identity or an unsupported codec we left untouched.
+    request_content_encoding: String,
+    /// Bytes accumulated across `on_http_response_body` calls that don't yet
+    /// form a complete `\n\n`-delimited SSE event (a `data:` frame can split
+    /// across Envoy body callbacks)
+    sse_buffer: Vec<u8>,
+    /// Concatenated streaming delta text (per the configured provider's
+    /// `extract_delta_text`) seen so far this stream, sent to OG output
+    /// detection in windows of `sse_window_bytes` and once more at the end
+    /// of the stream for whatever is left
+    sse_content_accum: String,
+    /// Original SSE frames received since the last window flush, in
+    /// arrival order, kept so a window's released content can rewrite the
+    /// upstream's actual frames in place (or forward them verbatim on
+    /// "pass") instead of synthesizing a new object. Drained on every flush.
+    sse_pending_frames: Vec<sse::PendingFrame>,
+    /// Shared-data cache key for the in-flight detection call, set right
+    /// before dispatch so the response can be written back into the cache
+    /// once it arrives
+    pending_cache_key: Option<String>,
+    /// Metric handles shared with `OGConnectorRoot`
+    metrics: metrics::Metrics,
+    /// Wall-clock time `call_og_api` dispatched, used to record `og_api_latency_ms`
+    dispatch_started_at_ms: Option<u64>,
+    /// `(path, body)` of the most recent OG API dispatch, kept so a failed
+    /// call can be retried without re-deriving the request.
+    pending_retry: Option<(String, Vec<u8>)>,
+    /// Retries already attempted for the current OG API call.
+    retry_count: u32,
+    /// Index into `OGConnectorConfig::backend_list()` last selected by
+    /// `call_og_api`, used to record dispatch health in `on_http_call_response`.
+    active_backend_index: Option<usize>,
+    /// Bytes of `sse_content_accum` already released downstream (as original,
+    /// anonymized, or restored content) by a previous window flush.
+    sse_emitted_bytes: usize,
+    /// Bytes at the front of the most recently dispatched detection window
+    /// that were only included as overlap context and must be stripped
+    /// before the returned action is applied, since that prefix was already
+    /// sent to the client by the previous window.
+    sse_window_overlap_len: usize,
+    /// Whether the in-flight windowed detection call covers the end of the
+    /// stream, so `handle_output_response` knows to terminate the SSE
+    /// response instead of waiting for more chunks.
+    sse_final_flush: bool,
+    /// `sse_content_accum` length as of the most recently dispatched
+    /// window's snapshot - the upper bound of what that window's action
+    /// applies to, since more bytes may keep arriving while it's in flight.
+    sse_window_end: usize,
+    /// Set when `end_of_stream` arrives while a windowed detection call is
+    /// still in flight, so no further `on_http_response_body` calls will
+    /// come and `handle_output_response` must close the stream out itself
+    /// once that call resolves.
+    sse_stream_ended: bool,
+    /// Whether this connection negotiated `Upgrade: websocket`, switching
+    /// the request/response body callbacks into frame-streaming mode
+    /// instead of the buffered-JSON pipeline above.
+    is_websocket: bool,
+    /// Tunneled bytes from the client not yet reassembled into a complete
+    /// WebSocket frame.
+    ws_req_buffer: Vec<u8>,
+    /// In-progress fragmented text/binary message (client -> model
+    /// direction), carried across `on_http_request_body` calls.
+    ws_req_fragment: Option<(u8, Vec<u8>)>,
+    /// Reassembled messages already parsed out of `ws_req_buffer` but not
+    /// yet forwarded or dispatched for detection.
+    ws_req_pending: VecDeque<websocket::Message>,
+    /// Frames already decided (pass-through or post-detection) waiting to
+    /// be flushed to the client-facing request body in one batch.
+    ws_req_forward_buf: Vec<u8>,
+    /// Original text of the WebSocket message currently awaiting an input
+    /// detection verdict, used as the fallback if the response can't be
+    /// parsed or carries no anonymized replacement.
+    ws_req_text_pending: Option<String>,
+    /// Tunneled bytes from the model not yet reassembled into a complete
+    /// WebSocket frame.
+    ws_resp_buffer: Vec<u8>,
+    /// In-progress fragmented text/binary message (model -> client
+    /// direction), carried across `on_http_response_body` calls.
+    ws_resp_fragment: Option<(u8, Vec<u8>)>,
+    /// Reassembled messages already parsed out of `ws_resp_buffer` but not
+    /// yet forwarded or dispatched for detection.
+    ws_resp_pending: VecDeque<websocket::Message>,
+    /// Frames already decided (pass-through or post-detection) waiting to
+    /// be flushed to the client-facing response body in one batch.
+    ws_resp_forward_buf: Vec<u8>,
+    /// Original text of the WebSocket message currently awaiting an output
+    /// detection verdict, used as the fallback if the response can't be
+    /// parsed or carries no anonymized/restored replacement.
+    ws_resp_text_pending: Option<String>,
+    /// Incremented on every client->server frame this connector masks, so
+    /// `next_ws_mask_key` never derives the same key twice in a row even if
+    /// called within the same millisecond.
+    ws_mask_counter: u64,
 }
 
 impl OGConnector {
@@ -415,8 +765,31 @@ impl OGConnector {
         log::warn!("[OG-LOCAL-RSP] Local response sent, request terminated: ctx={}", self.context_id);
     }
 
-    fn call_og_api(&self, path: &str, body: &[u8]) -> Result<u32, proxy_wasm::types::Status> {
+    fn now_ms(&self) -> u64 {
+        self.get_current_time()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0)
+    }
+
+    /// Fresh masking key for the next client->server WebSocket frame this
+    /// connector forwards - see `websocket::next_mask_key`.
+    fn next_ws_mask_key(&mut self) -> [u8; 4] {
+        self.ws_mask_counter = self.ws_mask_counter.wrapping_add(1);
+        websocket::next_mask_key(self.now_ms() ^ self.ws_mask_counter ^ (self.context_id as u64))
+    }
+
+    fn call_og_api(&mut self, path: &str, body: &[u8]) -> Result<u32, proxy_wasm::types::Status> {
+        self.call_og_api_avoiding(path, body, None)
+    }
+
+    /// Same as `call_og_api`, but excludes `avoid` from the initial backend
+    /// selection if any other backend is available - used by
+    /// `handle_og_api_failure` so a cross-call retry doesn't land back on the
+    /// backend the previous attempt already failed on (see `backends::select`).
+    fn call_og_api_avoiding(&mut self, path: &str, body: &[u8], avoid: Option<usize>) -> Result<u32, proxy_wasm::types::Status> {
         log::warn!("[OG-API] call_og_api START: ctx={}, path={}", self.context_id, path);
+        self.dispatch_started_at_ms = Some(self.now_ms());
 
         let config = match self.config.as_ref() {
             Some(c) => c,
@@ -426,13 +799,8 @@ impl OGConnector {
             }
         };
 
-        // og_cluster already contains full cluster name like "outbound|5002||openguardrails-local.dns"
-        let cluster = &config.og_cluster;
-
-        // Extract host from og_base_url (remove http:// or https://)
-        let host = config.og_base_url
-            .trim_start_matches("http://")
-            .trim_start_matches("https://");
+        let backend_list = config.backend_list();
+        let balance_strategy = config.balance_strategy;
 
         // Mask API key for logging (show first 10 and last 4 chars)
         let api_key_masked = if config.og_api_key.len() > 14 {
@@ -441,55 +809,241 @@ impl OGConnector {
             "***".to_string()
         };
 
-        log::warn!("[OG-API] dispatch_http_call PARAMS: ctx={}, cluster='{}', host='{}', path='{}', api_key={}, body_len={}, timeout_ms={}, consumer_id={:?}",
-            self.context_id, cluster, host, path, api_key_masked, body.len(), config.timeout_ms, self.consumer_id);
+        let auth_header = format!("Bearer {}", config.og_api_key);
+        let max_retries = config.max_retries;
+        let timeout = Duration::from_millis(config.timeout_ms);
+
+        let mut backend_index = backends::select(self, backend_list.len(), balance_strategy, avoid);
+        self.active_backend_index = Some(backend_index);
+        // og_cluster already contains full cluster name like "outbound|5002||openguardrails-local.dns"
+        let (mut cluster, mut raw_base_url) = backend_list[backend_index].clone();
+
+        // Extract host from og_base_url (remove http:// or https://)
+        let mut host = raw_base_url
+            .trim_start_matches("http://")
+            .trim_start_matches("https://")
+            .to_string();
+
+        log::warn!("[OG-API] dispatch_http_call PARAMS: ctx={}, backend={}/{}, cluster='{}', host='{}', path='{}', api_key={}, body_len={}, timeout_ms={}, consumer_id={:?}",
+            self.context_id, backend_index, backend_list.len(), cluster, host, path, api_key_masked, body.len(), timeout.as_millis(), self.consumer_id);
 
         // Log body preview (first 200 chars) for debugging
         let body_preview = String::from_utf8_lossy(body);
         log::warn!("[OG-API] Request body preview: {}", safe_truncate(&body_preview, 200));
 
-        // Build headers - include consumer ID if present for auto-discovery
-        let auth_header = format!("Bearer {}", config.og_api_key);
-        let mut headers = vec![
-            (":method", "POST"),
-            (":path", path),
-            (":authority", host),
-            ("content-type", "application/json"),
-            ("authorization", auth_header.as_str()),
-        ];
-
         // Add application ID header for automatic application discovery in OG
         let consumer_id_owned = self.consumer_id.clone();
         if let Some(ref consumer) = consumer_id_owned {
-            headers.push(("X-OG-Application-ID", consumer.as_str()));
             log::info!("[OG-API] Adding X-OG-Application-ID header: ctx={}, app_id={}", self.context_id, consumer);
         }
 
-        let result = self.dispatch_http_call(
-            &cluster,
-            headers,
-            Some(body),
-            vec![],
-            Duration::from_millis(config.timeout_ms),
-        );
+        // Build headers - include consumer ID if present for auto-discovery
+        fn build_headers<'a>(path: &'a str, host: &'a str, auth_header: &'a str, consumer_id: &'a Option<String>) -> Vec<(&'a str, &'a str)> {
+            let mut headers = vec![
+                (":method", "POST"),
+                (":path", path),
+                (":authority", host),
+                ("content-type", "application/json"),
+                ("authorization", auth_header),
+            ];
+            if let Some(consumer) = consumer_id {
+                headers.push(("X-OG-Application-ID", consumer.as_str()));
+            }
+            headers
+        }
+
+        let mut result = self.dispatch_http_call(&cluster, build_headers(path, &host, &auth_header, &consumer_id_owned), Some(body), vec![], timeout);
+        // A dispatch error here (as opposed to a non-200/timeout surfaced
+        // later in `on_http_call_response`) means the host rejected the call
+        // outright - e.g. the cluster is unknown - so retrying the same
+        // backend would just fail again. Record the failure against it and
+        // fail over to the next one for each retry attempt, same as the async failure
+        // path in `handle_og_api_failure` does.
+        while result.is_err() && self.retry_count < max_retries {
+            self.retry_count += 1;
+            self.record_backend_result(false);
+            // Explicitly avoid the backend that just failed: `unhealthy_after_failures`
+            // consecutive failures (the default is 3) haven't necessarily
+            // accumulated yet after a single retry, so without this the
+            // health-threshold-gated `select` would often hand back the same
+            // backend that just errored.
+            backend_index = backends::select(self, backend_list.len(), balance_strategy, Some(backend_index));
+            self.active_backend_index = Some(backend_index);
+            (cluster, raw_base_url) = backend_list[backend_index].clone();
+            host = raw_base_url
+                .trim_start_matches("http://")
+                .trim_start_matches("https://")
+                .to_string();
+            // proxy-wasm gives `HttpContext` no per-context timer to sleep on
+            // (only `RootContext::on_tick`, which can't reach back into a
+            // specific in-flight `HttpContext`), so this failover re-dispatches
+            // immediately rather than waiting out a backoff interval.
+            log::warn!("[OG-API] dispatch_http_call retrying on next backend: ctx={}, attempt={}/{}, backend={}/{}, cluster='{}'",
+                self.context_id, self.retry_count, max_retries, backend_index, backend_list.len(), cluster);
+            result = self.dispatch_http_call(&cluster, build_headers(path, &host, &auth_header, &consumer_id_owned), Some(body), vec![], timeout);
+        }
 
         match &result {
             Ok(token_id) => {
                 log::warn!("[OG-API] dispatch_http_call SUCCESS: ctx={}, token_id={}", self.context_id, token_id);
+                self.pending_retry = Some((path.to_string(), body.to_vec()));
+                if path == "/v1/gateway/process-input" {
+                    self.metrics.incr(self.metrics.requests_input_total);
+                } else {
+                    self.metrics.incr(self.metrics.requests_output_total);
+                }
             }
             Err(status) => {
                 log::error!("[OG-API] dispatch_http_call FAILED: ctx={}, status={:?}", self.context_id, status);
                 log::error!("[OG-API] Check if cluster '{}' exists in Envoy config. Run: curl localhost:15000/clusters | grep '{}'",
                     cluster, cluster.split("||").last().unwrap_or(cluster));
+                self.record_backend_result(false);
             }
         }
 
         result
     }
 
+    /// Feed the outcome of the most recent `call_og_api` dispatch back into
+    /// the shared-data backend health tracker so the next call routes around
+    /// a consistently failing backend.
+    fn record_backend_result(&mut self, success: bool) {
+        let Some(index) = self.active_backend_index else { return; };
+        let Some(config) = self.config.as_ref() else { return; };
+        let backend_count = config.backend_list().len();
+        let unhealthy_after_failures = config.unhealthy_after_failures;
+        let backend_cooldown_ms = config.backend_cooldown_ms;
+        backends::record_result(self, backend_count, index, success, unhealthy_after_failures, backend_cooldown_ms);
+    }
+
+    /// Called when an OG API call has definitively failed (dispatch error,
+    /// non-200 response, or timeout). Retries the call up to `max_retries`
+    /// times before applying `failure_mode`.
+    fn handle_og_api_failure(&mut self) {
+        let max_retries = self.config.as_ref().map(|c| c.max_retries).unwrap_or(0);
+        if self.retry_count < max_retries {
+            if let Some((path, body)) = self.pending_retry.clone() {
+                self.retry_count += 1;
+                log::warn!("[OG-API] Retrying failed call: ctx={}, attempt={}/{}", self.context_id, self.retry_count, max_retries);
+                let failed_backend = self.active_backend_index;
+                if self.call_og_api_avoiding(&path, &body, failed_backend).is_ok() {
+                    return;
+                }
+            }
+        }
+        self.apply_failure_mode();
+    }
+
+    /// Forward the original request/response untouched (`fail_open`) or
+    /// terminate it with a deterministic local block response (`fail_closed`),
+    /// once OG API retries are exhausted.
+    fn apply_failure_mode(&mut self) {
+        let failure_mode = self.config.as_ref().map(|c| c.failure_mode).unwrap_or_default();
+        let status_code = self.config.as_ref().map(|c| c.fail_closed_status_code).unwrap_or_else(default_fail_closed_status);
+        const UNAVAILABLE_BODY: &[u8] = br#"{"error":"security check unavailable"}"#;
+
+        match self.state {
+            ConnectorState::WaitingInputResponse => match failure_mode {
+                FailureMode::FailOpen => {
+                    log::warn!("[OG-API] OG API failed, fail_open: forwarding original request: ctx={}", self.context_id);
+                    self.state = ConnectorState::Initial;
+                    self.resume_http_request();
+                }
+                FailureMode::FailClosed => {
+                    log::warn!("[OG-API] OG API failed, fail_closed: blocking request: ctx={}", self.context_id);
+                    self.send_local_response(status_code, "application/json", UNAVAILABLE_BODY);
+                }
+            },
+            ConnectorState::WaitingOutputResponse => {
+                if let Some(proxy_resp) = self.pending_proxy_response.take() {
+                    match failure_mode {
+                        FailureMode::FailOpen => {
+                            log::warn!("[OG-API] OG API failed, fail_open: returning proxy response untouched: ctx={}", self.context_id);
+                            self.send_local_response(proxy_resp.code as u32, &proxy_resp.content_type, proxy_resp.body.as_bytes());
+                        }
+                        FailureMode::FailClosed => {
+                            log::warn!("[OG-API] OG API failed, fail_closed: blocking proxy response: ctx={}", self.context_id);
+                            self.send_local_response(status_code, "application/json", UNAVAILABLE_BODY);
+                        }
+                    }
+                } else {
+                    match failure_mode {
+                        FailureMode::FailOpen => {
+                            log::warn!("[OG-API] OG API failed, fail_open: forwarding original response: ctx={}", self.context_id);
+                            self.state = ConnectorState::Done;
+                            self.resume_http_response();
+                        }
+                        FailureMode::FailClosed => {
+                            log::warn!("[OG-API] OG API failed, fail_closed: blocking response: ctx={}", self.context_id);
+                            self.send_local_response(status_code, "application/json", UNAVAILABLE_BODY);
+                        }
+                    }
+                }
+            }
+            ConnectorState::WaitingWsInputResponse => match failure_mode {
+                FailureMode::FailOpen => self.resume_ws_request_forward(None),
+                FailureMode::FailClosed => self.close_websocket(websocket::CLOSE_POLICY_VIOLATION, "security check unavailable"),
+            },
+            ConnectorState::WaitingWsOutputResponse => match failure_mode {
+                FailureMode::FailOpen => self.resume_ws_response_forward(None),
+                FailureMode::FailClosed => self.close_websocket(websocket::CLOSE_POLICY_VIOLATION, "security check unavailable"),
+            },
+            _ => {}
+        }
+    }
+
+    /// What a `DataAction`-returning call site should do once `call_og_api`
+    /// has returned `Err` (dispatch failed even after internal retries):
+    /// pass the buffered body through untouched (`fail_open`) or terminate
+    /// the request/response with a local block (`fail_closed`).
+    fn on_dispatch_error(&mut self) -> DataAction {
+        let failure_mode = self.config.as_ref().map(|c| c.failure_mode).unwrap_or_default();
+        match failure_mode {
+            FailureMode::FailOpen => DataAction::Continue,
+            FailureMode::FailClosed => {
+                let status_code = self.config.as_ref().map(|c| c.fail_closed_status_code).unwrap_or_else(default_fail_closed_status);
+                log::warn!("[OG-API] dispatch failed, fail_closed: blocking: ctx={}", self.context_id);
+                self.send_local_response(status_code, "application/json", br#"{"error":"security check unavailable"}"#);
+                DataAction::StopIterationAndBuffer
+            }
+        }
+    }
+
+    /// Adapter for the configured upstream wire format (OpenAI by default).
+    fn format_adapter(&self) -> Box<dyn formats::UpstreamFormat> {
+        let provider = self.config.as_ref().map(|c| c.provider).unwrap_or_default();
+        formats::adapter_for(provider)
+    }
+
+    /// Populate the content-keyed cache from a fresh OG API response, if a
+    /// lookup was pending and the decision is safe to replay later.
+    fn maybe_cache_decision(&mut self, body: &[u8]) {
+        let Some(key) = self.pending_cache_key.take() else {
+            return;
+        };
+        let Some(max_bytes) = self.config.as_ref().map(|c| c.cache_max_bytes) else {
+            return;
+        };
+        let action = serde_json::from_slice::<serde_json::Value>(body)
+            .ok()
+            .and_then(|v| v.get("action").and_then(|a| a.as_str()).map(|s| s.to_string()));
+        match action {
+            Some(action) if Self::is_cacheable_action(&action) => {
+                log::warn!("[OG-CACHE] Storing decision: ctx={}, key={}, action={}", self.context_id, key, action);
+                cache::put(self, &key, body, max_bytes);
+            }
+            Some(action) => {
+                log::warn!("[OG-CACHE] Not caching non-replayable action: ctx={}, action={}", self.context_id, action);
+            }
+            None => {
+                log::warn!("[OG-CACHE] Could not determine action, not caching: ctx={}", self.context_id);
+            }
+        }
+    }
+
     fn parse_messages(&self, body: &[u8]) -> Option<Vec<serde_json::Value>> {
         let json: serde_json::Value = serde_json::from_slice(body).ok()?;
-        json.get("messages")?.as_array().cloned()
+        self.format_adapter().extract_messages(&json)
     }
 
     fn check_streaming(&self, body: &[u8]) -> bool {
@@ -500,8 +1054,20 @@ impl OGConnector {
         }
     }
 
-    fn build_input_request(&self, messages: Vec<serde_json::Value>) -> Vec<u8> {
+    fn build_input_request(&self, mut messages: Vec<serde_json::Value>) -> Vec<u8> {
         let config = self.config.as_ref().unwrap();
+        let mut truncated = false;
+        if config.max_inspect_bytes > 0 {
+            for message in messages.iter_mut() {
+                if let Some(content) = message.get("content").and_then(|c| c.as_str()) {
+                    let (bounded, was_truncated) = truncate_for_inspection(content, config.max_inspect_bytes);
+                    if was_truncated {
+                        message["content"] = serde_json::Value::String(bounded);
+                        truncated = true;
+                    }
+                }
+            }
+        }
         let request = OGInputRequest {
             messages,
             application_id: if config.application_id.is_empty() {
@@ -509,14 +1075,20 @@ impl OGConnector {
             } else {
                 Some(config.application_id.clone())
             },
+            truncated,
         };
         serde_json::to_vec(&request).unwrap_or_default()
     }
 
     fn build_output_request(&self, content: &str) -> Vec<u8> {
         let config = self.config.as_ref().unwrap();
+        let (content, truncated) = if config.max_inspect_bytes > 0 {
+            truncate_for_inspection(content, config.max_inspect_bytes)
+        } else {
+            (content.to_string(), false)
+        };
         let request = OGOutputRequest {
-            content: content.to_string(),
+            content,
             session_id: self.session_id.clone(),
             restore_mapping: self.restore_mapping.clone(),  // Include mapping for restoration
             application_id: if config.application_id.is_empty() {
@@ -526,13 +1098,14 @@ impl OGConnector {
             },
             // Include input messages as context for output detection
             messages: self.input_messages.clone(),
+            truncated,
         };
         serde_json::to_vec(&request).unwrap_or_default()
     }
 
     fn rebuild_request_body(&self, messages: &[serde_json::Value]) -> Vec<u8> {
         if let Ok(mut json) = serde_json::from_slice::<serde_json::Value>(&self.request_body) {
-            json["messages"] = serde_json::Value::Array(messages.to_vec());
+            self.format_adapter().apply_messages(&mut json, messages);
             serde_json::to_vec(&json).unwrap_or_else(|_| self.request_body.clone())
         } else {
             self.request_body.clone()
@@ -541,14 +1114,7 @@ impl OGConnector {
 
     fn rebuild_response_body(&self, new_content: &str) -> Vec<u8> {
         if let Ok(mut json) = serde_json::from_slice::<serde_json::Value>(&self.response_body) {
-            // Update content in choices[0].message.content
-            if let Some(choices) = json.get_mut("choices").and_then(|c| c.as_array_mut()) {
-                if let Some(first_choice) = choices.get_mut(0) {
-                    if let Some(message) = first_choice.get_mut("message") {
-                        message["content"] = serde_json::Value::String(new_content.to_string());
-                    }
-                }
-            }
+            self.format_adapter().apply_text(&mut json, new_content);
             serde_json::to_vec(&json).unwrap_or_else(|_| self.response_body.clone())
         } else {
             self.response_body.clone()
@@ -557,16 +1123,26 @@ impl OGConnector {
 
     fn extract_response_content(&self) -> Option<String> {
         let json: serde_json::Value = serde_json::from_slice(&self.response_body).ok()?;
-        json.get("choices")?
-            .get(0)?
-            .get("message")?
-            .get("content")?
-            .as_str()
-            .map(|s| s.to_string())
+        self.format_adapter().extract_text(&json)
     }
 
-    /// Extract content from a proxy response body (OpenAI format)
-    fn extract_content_from_body(body: &str) -> Option<String> {
+    /// Extract content from a proxy response body. `content_type` selects
+    /// between the OpenAI single-JSON shape and an SSE stream of chunks -
+    /// the private model's response is returned to us already fully
+    /// buffered by OG, so even the SSE case is one synchronous extraction
+    /// rather than the incremental windowing `handle_sse_response_body` does
+    /// for the normal upstream path.
+    fn extract_content_from_body(&self, body: &str, content_type: &str) -> Option<String> {
+        if content_type.contains("text/event-stream") {
+            let format = self.format_adapter();
+            let mut buffer = body.as_bytes().to_vec();
+            let content: String = sse::drain_complete_frames(&mut buffer)
+                .iter()
+                .filter_map(|frame| frame.delta_content(format.as_ref()))
+                .collect();
+            return if content.is_empty() { None } else { Some(content) };
+        }
+
         let json: serde_json::Value = serde_json::from_str(body).ok()?;
         json.get("choices")?
             .get(0)?
@@ -576,8 +1152,13 @@ impl OGConnector {
             .map(|s| s.to_string())
     }
 
-    /// Rebuild proxy response body with new content
-    fn rebuild_proxy_response_body(original_body: &str, new_content: &str) -> String {
+    /// Rebuild proxy response body with new content, mirroring the shape
+    /// `extract_content_from_body` parsed it as.
+    fn rebuild_proxy_response_body(original_body: &str, content_type: &str, new_content: &str) -> String {
+        if content_type.contains("text/event-stream") {
+            return String::from_utf8_lossy(&sse::render_content_event(new_content)).to_string();
+        }
+
         if let Ok(mut json) = serde_json::from_str::<serde_json::Value>(original_body) {
             if let Some(choices) = json.get_mut("choices").and_then(|c| c.as_array_mut()) {
                 if let Some(first_choice) = choices.get_mut(0) {
@@ -592,6 +1173,278 @@ impl OGConnector {
         }
     }
 
+    /// Fold newly-arrived bytes into the persistent SSE buffer and extract
+    /// the streaming delta text of every complete frame found so far, via
+    /// the configured provider's `format_adapter()` so non-OpenAI streams
+    /// (Anthropic `content_block_delta`, Gemini streamed `parts[].text`)
+    /// accumulate content too instead of silently staying empty. Every frame
+    /// is also queued in `sse_pending_frames` - text-bearing or not - so the
+    /// next window flush can rewrite/forward the upstream's actual frames
+    /// instead of dropping the ones with no text to inspect (tool calls,
+    /// other `n>1` choices, keep-alives, ...). The upstream's own `[DONE]`
+    /// is never queued; whichever flush ends the stream emits its own.
+    fn accumulate_sse_chunk(&mut self, chunk: &[u8]) {
+        self.sse_buffer.extend_from_slice(chunk);
+        let format = self.format_adapter();
+        for frame in sse::drain_complete_frames(&mut self.sse_buffer) {
+            if frame.is_done() {
+                continue;
+            }
+            match frame.delta_content(format.as_ref()) {
+                Some(content) => {
+                    self.sse_content_accum.push_str(&content);
+                    self.sse_pending_frames.push(sse::PendingFrame::Text(frame.raw_data));
+                }
+                None => self.sse_pending_frames.push(sse::PendingFrame::Raw(frame.raw_data)),
+            }
+        }
+    }
+
+    /// Bytes accumulated since the last window flush (or since the stream
+    /// started, if no window has flushed yet) that haven't been sent to OG.
+    fn sse_pending_new_bytes(&self) -> usize {
+        self.sse_content_accum.len().saturating_sub(self.sse_emitted_bytes)
+    }
+
+    /// Dispatch output detection on everything accumulated since the last
+    /// window flush, prefixed with `sse_overlap_bytes` of already-released
+    /// content so sensitive text split across a window boundary is still
+    /// seen together by OG. The overlap is only for detection context - it
+    /// is never re-emitted downstream, since it was already sent to the
+    /// client by the previous window. `SSE_OVERLAP_MARKER` is inserted at the
+    /// overlap/new-content boundary before the window is sent to OG, so
+    /// `emit_sse_window_action` can find where the already-released prefix
+    /// ends in the returned replacement even though anonymize/restore can
+    /// change its byte length (see the SSE branch of `handle_output_response`,
+    /// which calls into `emit_sse_window_action` with whatever action content
+    /// comes back).
+    fn dispatch_sse_window(&mut self, is_final: bool) -> DataAction {
+        let config = self.config.as_ref().unwrap();
+        let overlap_bytes = config.sse_overlap_bytes;
+        let window_start = floor_char_boundary(&self.sse_content_accum, self.sse_emitted_bytes.saturating_sub(overlap_bytes));
+        self.sse_window_overlap_len = self.sse_emitted_bytes.saturating_sub(window_start);
+        self.sse_window_end = self.sse_content_accum.len();
+        let window_text = self.sse_content_accum[window_start..self.sse_window_end].to_string();
+
+        log::warn!("[OG-SSE] Flushing window, calling process-output: ctx={}, window_len={}, overlap_len={}, is_final={}",
+            self.context_id, window_text.len(), self.sse_window_overlap_len, is_final);
+
+        let mut marked_window_text = window_text;
+        marked_window_text.insert_str(self.sse_window_overlap_len, SSE_OVERLAP_MARKER);
+        let request_body = self.build_output_request(&marked_window_text);
+        self.retry_count = 0;
+        self.sse_final_flush = is_final;
+        match self.call_og_api("/v1/gateway/process-output", &request_body) {
+            Ok(token_id) => {
+                log::warn!("[OG-SSE] API call dispatched: ctx={}, token_id={}, state -> WaitingOutputResponse",
+                    self.context_id, token_id);
+                self.state = ConnectorState::WaitingOutputResponse;
+                DataAction::StopIterationAndBuffer
+            }
+            Err(e) => {
+                log::error!("[OG-SSE] API call FAILED: ctx={}, error={:?}", self.context_id, e);
+                self.on_dispatch_error()
+            }
+        }
+    }
+
+    /// Forward the window's queued frames exactly as the upstream sent
+    /// them - the "pass" action (the overwhelming majority of windows) and
+    /// the fail-open fallback, appending the terminal `[DONE]` marker only
+    /// if this is the last window of the stream. Every original field
+    /// (`id`/`model`/`usage`/`finish_reason`, other choices, tool-call
+    /// deltas, ...) reaches the client untouched, and `sse_pending_frames`
+    /// is drained since this window's frames are now released.
+    fn emit_sse_window_passthrough(&mut self, is_final: bool) {
+        let frames = std::mem::take(&mut self.sse_pending_frames);
+        let payload = sse::render_passthrough(&frames, is_final);
+        self.set_http_response_body(0, i32::MAX as usize, &payload);
+    }
+
+    /// Rewrite the window's queued frames with `content` (OG's anonymized/
+    /// restored replacement) via the configured provider's
+    /// `ResponseFormat::render_delta_event`, rather than synthesizing a new
+    /// object, appending the terminal `[DONE]` marker only if this is the
+    /// last window of the stream. Drains `sse_pending_frames` like
+    /// `emit_sse_window_passthrough`.
+    fn emit_sse_window_rewritten(&mut self, content: &str, is_final: bool) {
+        let frames = std::mem::take(&mut self.sse_pending_frames);
+        let format = self.format_adapter();
+        let payload = sse::render_rewritten(&frames, content, format.as_ref(), is_final);
+        self.set_http_response_body(0, i32::MAX as usize, &payload);
+    }
+
+    /// Emit an OG-returned (anonymized/restored) replacement for the current
+    /// window, releasing only what comes after `SSE_OVERLAP_MARKER` -
+    /// `dispatch_sse_window` inserted it at the overlap/new-content boundary,
+    /// so this is the already-released prefix regardless of how
+    /// anonymize/restore changed the surrounding text's byte length. If OG
+    /// didn't return the marker (stripped, or cut off by `max_inspect_bytes`
+    /// truncation), the split point can't be trusted, so fail open: forward
+    /// the window's original frames unmodified rather than risk emitting a
+    /// corrupted splice.
+    fn emit_sse_window_action(&mut self, replacement: &str, is_final: bool) {
+        match replacement.find(SSE_OVERLAP_MARKER) {
+            Some(marker_pos) => {
+                let content = replacement[marker_pos + SSE_OVERLAP_MARKER.len()..].to_string();
+                self.emit_sse_window_rewritten(&content, is_final);
+            }
+            None => {
+                log::warn!("[OG-SSE] Replacement missing overlap marker, failing open: ctx={}", self.context_id);
+                self.emit_sse_window_passthrough(is_final);
+            }
+        }
+    }
+
+    /// Handle one `on_http_response_body` callback for an SSE (`text/event-stream`)
+    /// response: fold the chunk into the accumulator, and either flush a
+    /// detection window once enough new content has built up, or (at the
+    /// end of the stream) flush whatever is left, however small.
+    fn handle_sse_response_body(&mut self, body_size: usize, end_of_stream: bool) -> DataAction {
+        if let Some(chunk) = self.get_http_response_body(0, body_size) {
+            log::warn!("[OG-SSE] Accumulating chunk: ctx={}, chunk_len={}, end_of_stream={}",
+                self.context_id, chunk.len(), end_of_stream);
+            self.accumulate_sse_chunk(&chunk);
+        }
+
+        // A window dispatch is already in flight; keep buffering until it
+        // resolves in `handle_output_response`, which decides whether to
+        // flush another window or finish the stream. If the stream ends
+        // while we're waiting, no further calls here will come, so remember
+        // that the in-flight resolution must close things out.
+        if self.state == ConnectorState::WaitingOutputResponse {
+            if end_of_stream {
+                self.sse_stream_ended = true;
+            }
+            return DataAction::StopIterationAndBuffer;
+        }
+
+        if !end_of_stream {
+            let config = self.config.as_ref().unwrap();
+            if config.sse_window_bytes > 0 && self.sse_pending_new_bytes() >= config.sse_window_bytes {
+                return self.dispatch_sse_window(false);
+            }
+            return DataAction::StopIterationAndBuffer;
+        }
+
+        if self.sse_pending_new_bytes() == 0 {
+            if self.sse_emitted_bytes > 0 {
+                // Earlier windows already released content without the
+                // terminal marker; forward any text-free frames queued since
+                // (finish_reason, usage, ...) and close the stream out now.
+                log::warn!("[OG-SSE] Stream ended with no remaining content, closing out: ctx={}", self.context_id);
+                let mut payload = sse::render_passthrough(&std::mem::take(&mut self.sse_pending_frames), false);
+                payload.extend_from_slice(&sse::render_done());
+                self.set_http_response_body(0, i32::MAX as usize, &payload);
+                self.resume_http_response();
+                self.state = ConnectorState::Done;
+                return DataAction::Continue;
+            }
+            log::warn!("[OG-SSE] Stream ended with no delta content to inspect: ctx={}", self.context_id);
+            return DataAction::Continue;
+        }
+
+        self.dispatch_sse_window(true)
+    }
+
+    /// Apply a previously-cached `/v1/gateway/process-input` decision
+    /// synchronously, without going through `dispatch_http_call`. Only the
+    /// deterministic, replayable actions are cached (see `on_http_call_response`);
+    /// anything else falls through to a live detection call.
+    fn apply_cached_input_decision(&mut self, body: &[u8]) -> DataAction {
+        let response: OGInputResponse = match serde_json::from_slice(body) {
+            Ok(r) => r,
+            Err(e) => {
+                log::error!("[OG-CACHE] Failed to parse cached input decision, ignoring cache: ctx={}, error={}", self.context_id, e);
+                return DataAction::Continue;
+            }
+        };
+
+        log::warn!("[OG-CACHE] Applying cached input decision: ctx={}, action={}", self.context_id, response.action);
+        self.session_id = response.session_id;
+        self.restore_mapping = response.restore_mapping;
+
+        match response.action.as_str() {
+            "block" => {
+                self.metrics.incr(self.metrics.blocked_total);
+                if let Some(block_resp) = response.block_response {
+                    self.send_local_response(block_resp.code as u32, &block_resp.content_type, block_resp.body.as_bytes());
+                }
+                DataAction::StopIterationAndBuffer
+            }
+            "replace" => {
+                self.metrics.incr(self.metrics.replaced_total);
+                if let Some(replace_resp) = response.replace_response {
+                    self.send_local_response(replace_resp.code as u32, &replace_resp.content_type, replace_resp.body.as_bytes());
+                }
+                DataAction::StopIterationAndBuffer
+            }
+            "anonymize" => {
+                self.metrics.incr(self.metrics.anonymized_total);
+                if let Some(messages) = response.anonymized_messages {
+                    let new_body = self.rebuild_request_body(&messages);
+                    self.set_http_request_body(0, i32::MAX as usize, &new_body);
+                }
+                DataAction::Continue
+            }
+            _ => DataAction::Continue,
+        }
+    }
+
+    /// Apply a previously-cached `/v1/gateway/process-output` decision
+    /// synchronously. Mirrors the non-SSE, non-proxy-response branch of
+    /// `handle_output_response`.
+    fn apply_cached_output_decision(&mut self, body: &[u8]) -> DataAction {
+        let response: OGOutputResponse = match serde_json::from_slice(body) {
+            Ok(r) => r,
+            Err(e) => {
+                log::error!("[OG-CACHE] Failed to parse cached output decision, ignoring cache: ctx={}, error={}", self.context_id, e);
+                return DataAction::Continue;
+            }
+        };
+
+        log::warn!("[OG-CACHE] Applying cached output decision: ctx={}, action={}", self.context_id, response.action);
+        match response.action.as_str() {
+            "block" => {
+                self.metrics.incr(self.metrics.blocked_total);
+                if let Some(block_resp) = response.block_response {
+                    self.set_http_response_body(0, i32::MAX as usize, block_resp.body.as_bytes());
+                }
+            }
+            "anonymize" => {
+                self.metrics.incr(self.metrics.anonymized_total);
+                if let Some(anonymized) = response.anonymized_content {
+                    let new_body = self.rebuild_response_body(&anonymized);
+                    self.set_http_response_body(0, i32::MAX as usize, &new_body);
+                }
+            }
+            "restore" => {
+                if let Some(restored) = response.restored_content {
+                    let new_body = self.rebuild_response_body(&restored);
+                    self.set_http_response_body(0, i32::MAX as usize, &new_body);
+                }
+            }
+            _ => {}
+        }
+        DataAction::Continue
+    }
+
+    /// Whether an OG decision is safe to cache and replay for identical
+    /// future content. Only `pass`/`block`/`replace` qualify: those verdicts
+    /// depend on nothing but the inspected content, so replaying one onto
+    /// byte-identical content from an unrelated request is always correct.
+    /// `anonymize`/`restore` are deliberately excluded even though OG returns
+    /// them for a given content hash just as reproducibly in the common case
+    /// - they carry `session_id`/`restore_mapping` that this connector has no
+    /// way to verify is safe to hand to a different session, and caching them
+    /// would assume OG's anonymizer is a pure function of content with no
+    /// session-scoped side effects, which isn't a documented guarantee.
+    /// `proxy_response`/`switch_private_model` route through live
+    /// private-model state and are never cached either.
+    fn is_cacheable_action(action: &str) -> bool {
+        matches!(action, "pass" | "block" | "replace")
+    }
+
     fn handle_input_response(&mut self, body: &[u8]) -> Action {
         log::warn!("[OG-INPUT-RSP] handle_input_response: ctx={}, body_len={}", self.context_id, body.len());
 
@@ -618,6 +1471,7 @@ impl OGConnector {
         match response.action.as_str() {
             "block" => {
                 log::warn!("[OG-INPUT-RSP] Action=BLOCK: ctx={}", self.context_id);
+                self.metrics.incr(self.metrics.blocked_total);
                 if let Some(block_resp) = response.block_response {
                     log::warn!("[OG-INPUT-RSP] Sending block response: ctx={}, code={}, body_len={}",
                         self.context_id, block_resp.code, block_resp.body.len());
@@ -634,6 +1488,7 @@ impl OGConnector {
             }
             "replace" => {
                 log::warn!("[OG-INPUT-RSP] Action=REPLACE: ctx={}", self.context_id);
+                self.metrics.incr(self.metrics.replaced_total);
                 if let Some(replace_resp) = response.replace_response {
                     log::warn!("[OG-INPUT-RSP] Sending replace response: ctx={}, code={}, body_len={}",
                         self.context_id, replace_resp.code, replace_resp.body.len());
@@ -651,6 +1506,7 @@ impl OGConnector {
             "anonymize" => {
                 log::warn!("[OG-INPUT-RSP] Action=ANONYMIZE: ctx={}, session_id={:?}, restore_mapping_count={:?}",
                     self.context_id, self.session_id, self.restore_mapping.as_ref().map(|m| m.len()));
+                self.metrics.incr(self.metrics.anonymized_total);
                 // Keep state as Initial to allow response processing for restoration
                 self.state = ConnectorState::Initial;
 
@@ -680,7 +1536,7 @@ impl OGConnector {
                     let config = self.config.as_ref().unwrap();
                     if config.enable_output_detection {
                         // Extract content from proxy response for output detection
-                        if let Some(content) = Self::extract_content_from_body(&proxy_resp.body) {
+                        if let Some(content) = self.extract_content_from_body(&proxy_resp.body, &proxy_resp.content_type) {
                             log::warn!("[OG-INPUT-RSP] Output detection enabled, calling process-output: ctx={}, content_len={}",
                                 self.context_id, content.len());
 
@@ -689,6 +1545,7 @@ impl OGConnector {
 
                             // Build and send output detection request
                             let request_body = self.build_output_request(&content);
+                            self.retry_count = 0;
                             match self.call_og_api("/v1/gateway/process-output", &request_body) {
                                 Ok(token_id) => {
                                     log::warn!("[OG-INPUT-RSP] Output detection dispatched: ctx={}, token_id={}",
@@ -697,15 +1554,10 @@ impl OGConnector {
                                     return Action::Pause;
                                 }
                                 Err(e) => {
-                                    log::error!("[OG-INPUT-RSP] Output detection call failed: ctx={}, error={:?}, returning proxy response directly",
+                                    log::error!("[OG-INPUT-RSP] Output detection call failed: ctx={}, error={:?}",
                                         self.context_id, e);
-                                    // Fall through to return proxy response directly
-                                    let pending = self.pending_proxy_response.take().unwrap();
-                                    self.send_local_response(
-                                        pending.code as u32,
-                                        &pending.content_type,
-                                        pending.body.as_bytes(),
-                                    );
+                                    self.state = ConnectorState::WaitingOutputResponse;
+                                    self.apply_failure_mode();
                                     return Action::Pause;
                                 }
                             }
@@ -834,6 +1686,7 @@ impl OGConnector {
             match response.action.as_str() {
                 "block" => {
                     log::warn!("[OG-OUTPUT-RSP] Action=BLOCK for proxy response: ctx={}", self.context_id);
+                    self.metrics.incr(self.metrics.blocked_total);
                     if let Some(block_resp) = response.block_response {
                         log::warn!("[OG-OUTPUT-RSP] Sending block response: ctx={}, body_len={}",
                             self.context_id, block_resp.body.len());
@@ -856,7 +1709,7 @@ impl OGConnector {
                     if let Some(restored) = response.restored_content {
                         log::warn!("[OG-OUTPUT-RSP] Restoring content in proxy response: ctx={}, content_len={}",
                             self.context_id, restored.len());
-                        let new_body = Self::rebuild_proxy_response_body(&proxy_resp.body, &restored);
+                        let new_body = Self::rebuild_proxy_response_body(&proxy_resp.body, &proxy_resp.content_type, &restored);
                         log::warn!("[OG-OUTPUT-RSP] Sending restored proxy response: ctx={}, new_len={}",
                             self.context_id, new_body.len());
                         self.send_local_response(
@@ -883,13 +1736,83 @@ impl OGConnector {
                     );
                 }
             }
+            self.state = ConnectorState::Done;
             return Action::Pause;
         }
 
         // Normal upstream response handling
+        if self.is_sse_response {
+            let mut blocked = false;
+            // More delta content may have arrived while this window's
+            // detection call was in flight. If the stream already ended
+            // and nothing new showed up, this window is the last one even
+            // though it wasn't dispatched as such.
+            let more_pending = self.sse_content_accum.len() > self.sse_window_end;
+            let final_release = self.sse_final_flush || (self.sse_stream_ended && !more_pending);
+
+            match response.action.as_str() {
+                "block" => {
+                    log::warn!("[OG-OUTPUT-RSP] Action=BLOCK (SSE): ctx={}", self.context_id);
+                    self.metrics.incr(self.metrics.blocked_total);
+                    let message = response
+                        .block_response
+                        .map(|r| r.body)
+                        .unwrap_or_else(|| "Response blocked due to security policy.".to_string());
+                    self.set_http_response_body(0, i32::MAX as usize, &sse::render_block_event(&message));
+                    self.sse_pending_frames.clear();
+                    blocked = true;
+                }
+                "anonymize" => {
+                    log::warn!("[OG-OUTPUT-RSP] Action=ANONYMIZE (SSE): ctx={}", self.context_id);
+                    self.metrics.incr(self.metrics.anonymized_total);
+                    match response.anonymized_content {
+                        Some(anonymized) => self.emit_sse_window_action(&anonymized, final_release),
+                        None => {
+                            log::error!("[OG-OUTPUT-RSP] Anonymize action but no anonymized_content, forwarding window as-is: ctx={}", self.context_id);
+                            self.emit_sse_window_passthrough(final_release);
+                        }
+                    }
+                }
+                "restore" => {
+                    log::warn!("[OG-OUTPUT-RSP] Action=RESTORE (SSE): ctx={}", self.context_id);
+                    match response.restored_content {
+                        Some(restored) => self.emit_sse_window_action(&restored, final_release),
+                        None => {
+                            log::warn!("[OG-OUTPUT-RSP] Restore action but no restored_content, forwarding window as-is: ctx={}", self.context_id);
+                            self.emit_sse_window_passthrough(final_release);
+                        }
+                    }
+                }
+                _ => {
+                    log::warn!("[OG-OUTPUT-RSP] Action=PASS (SSE): ctx={}, forwarding window as-is", self.context_id);
+                    self.emit_sse_window_passthrough(final_release);
+                }
+            }
+
+            self.sse_emitted_bytes = self.sse_window_end;
+            self.resume_http_response();
+
+            if blocked || final_release {
+                log::warn!("[OG-OUTPUT-RSP] SSE stream finished: ctx={}", self.context_id);
+                self.state = ConnectorState::Done;
+            } else if self.sse_stream_ended {
+                // `more_pending` was true: content kept arriving after this
+                // window was dispatched, and the stream already ended, so
+                // no further `on_http_response_body` call will trigger the
+                // next flush - dispatch it directly.
+                log::warn!("[OG-OUTPUT-RSP] Stream ended during window flush, dispatching final window: ctx={}", self.context_id);
+                let _ = self.dispatch_sse_window(true);
+            } else {
+                log::warn!("[OG-OUTPUT-RSP] SSE window released, awaiting further chunks: ctx={}", self.context_id);
+                self.state = ConnectorState::Initial;
+            }
+            return Action::Continue;
+        }
+
         match response.action.as_str() {
             "block" => {
                 log::warn!("[OG-OUTPUT-RSP] Action=BLOCK: ctx={}", self.context_id);
+                self.metrics.incr(self.metrics.blocked_total);
                 if let Some(block_resp) = response.block_response {
                     log::warn!("[OG-OUTPUT-RSP] Replacing response with block: ctx={}, body_len={}",
                         self.context_id, block_resp.body.len());
@@ -900,6 +1823,7 @@ impl OGConnector {
             }
             "anonymize" => {
                 log::warn!("[OG-OUTPUT-RSP] Action=ANONYMIZE: ctx={}", self.context_id);
+                self.metrics.incr(self.metrics.anonymized_total);
                 if let Some(anonymized) = response.anonymized_content {
                     log::warn!("[OG-OUTPUT-RSP] Anonymizing content: ctx={}, content_len={}", self.context_id, anonymized.len());
                     let new_body = self.rebuild_response_body(&anonymized);
@@ -927,9 +1851,321 @@ impl OGConnector {
         }
 
         log::warn!("[OG-OUTPUT-RSP] Resuming response: ctx={}", self.context_id);
+        self.state = ConnectorState::Done;
         self.resume_http_response();
         Action::Continue
     }
+
+    // ============= WebSocket frame streaming =============
+    //
+    // Both directions run detection: client -> model through
+    // `handle_ws_request_body`/`process-input`, model -> client through
+    // `handle_ws_response_body`/`process-output`. Each direction has its own
+    // reassembly buffer, pending-message queue and forward buffer, and its
+    // own `ConnectorState::WaitingWs{Input,Output}Response` slot, so a
+    // detection call in flight on one direction never blocks the other.
+
+    /// Reassemble client -> model frames and run each complete text message
+    /// through input detection before forwarding it.
+    fn handle_ws_request_body(&mut self, body_size: usize) -> DataAction {
+        // A detection call for an earlier message is still in flight; keep
+        // accumulating without forwarding so frame order is preserved.
+        if self.state == ConnectorState::WaitingWsInputResponse {
+            return DataAction::StopIterationAndBuffer;
+        }
+
+        if let Some(chunk) = self.get_http_request_body(0, body_size) {
+            self.ws_req_buffer.extend_from_slice(&chunk);
+        }
+
+        let messages = websocket::drain_messages(&mut self.ws_req_buffer, &mut self.ws_req_fragment);
+        self.ws_req_pending.extend(messages);
+
+        self.forward_ws_request_messages()
+    }
+
+    /// Drain `ws_req_pending`, rebuilding pass-through frames into
+    /// `ws_req_forward_buf` and pausing to dispatch detection on the first
+    /// text message, if input detection is enabled.
+    fn forward_ws_request_messages(&mut self) -> DataAction {
+        let detect = self.config.as_ref().map(|c| c.enable_input_detection).unwrap_or(false);
+
+        while let Some(message) = self.ws_req_pending.pop_front() {
+            if detect {
+                if let websocket::Message::Text(text) = &message {
+                    return self.dispatch_ws_input_text(text.clone());
+                }
+            }
+            let mask_key = self.next_ws_mask_key();
+            self.ws_req_forward_buf.extend_from_slice(&websocket::build_message_frame(&message, Some(mask_key)));
+        }
+
+        if self.ws_req_forward_buf.is_empty() {
+            return DataAction::StopIterationAndBuffer;
+        }
+        let out = std::mem::take(&mut self.ws_req_forward_buf);
+        self.set_http_request_body(0, i32::MAX as usize, &out);
+        DataAction::Continue
+    }
+
+    /// Dispatch input detection for one reassembled WebSocket text message,
+    /// mirroring the JSON path's `call_og_api("/v1/gateway/process-input", ...)`
+    /// but scoped to a single frame's text instead of the whole `messages[]`.
+    fn dispatch_ws_input_text(&mut self, text: String) -> DataAction {
+        let messages = vec![serde_json::json!({ "role": "user", "content": text })];
+        self.ws_req_text_pending = Some(text);
+        let request_body = self.build_input_request(messages);
+        self.retry_count = 0;
+        match self.call_og_api("/v1/gateway/process-input", &request_body) {
+            Ok(token_id) => {
+                log::warn!("[OG-WS-REQ] Input detection dispatched: ctx={}, token_id={}", self.context_id, token_id);
+                self.state = ConnectorState::WaitingWsInputResponse;
+                DataAction::StopIterationAndBuffer
+            }
+            Err(e) => {
+                log::error!("[OG-WS-REQ] Input detection dispatch failed: ctx={}, error={:?}", self.context_id, e);
+                self.on_dispatch_error()
+            }
+        }
+    }
+
+    /// Handle OG's verdict for the in-flight WebSocket text message: forward
+    /// the (possibly anonymized) text as a fresh text frame, or close the
+    /// connection on block/replace - there's no response body to carry a
+    /// block/replace response over an already-upgraded connection.
+    fn handle_ws_input_response(&mut self, body: &[u8]) {
+        let response: OGInputResponse = match serde_json::from_slice(body) {
+            Ok(r) => r,
+            Err(e) => {
+                log::error!("[OG-WS-REQ] Failed to parse input response: ctx={}, error={}", self.context_id, e);
+                self.resume_ws_request_forward(None);
+                return;
+            }
+        };
+
+        log::warn!("[OG-WS-REQ] Parsed response: ctx={}, action={}, request_id={}",
+            self.context_id, response.action, response.request_id);
+
+        self.session_id = response.session_id;
+        self.restore_mapping = response.restore_mapping;
+
+        match response.action.as_str() {
+            "block" | "replace" => {
+                log::warn!("[OG-WS-REQ] Action={}: closing connection with policy violation: ctx={}",
+                    response.action, self.context_id);
+                self.metrics.incr(self.metrics.blocked_total);
+                let reason = response.block_response.as_ref().map(|r| r.body.clone())
+                    .or_else(|| response.replace_response.as_ref().map(|r| r.body.clone()))
+                    .unwrap_or_else(|| "blocked by content policy".to_string());
+                self.ws_req_text_pending = None;
+                self.close_websocket(websocket::CLOSE_POLICY_VIOLATION, &safe_truncate(&reason, 123));
+            }
+            "anonymize" => {
+                log::warn!("[OG-WS-REQ] Action=ANONYMIZE: ctx={}", self.context_id);
+                self.metrics.incr(self.metrics.anonymized_total);
+                let text = response.anonymized_messages
+                    .as_ref()
+                    .and_then(|m| m.first())
+                    .and_then(|m| m.get("content"))
+                    .and_then(|c| c.as_str())
+                    .map(|s| s.to_string());
+                self.resume_ws_request_forward(text);
+            }
+            _ => {
+                self.resume_ws_request_forward(None);
+            }
+        }
+    }
+
+    /// Forward the decided text (the anonymized replacement, or the
+    /// original if none was given), then keep draining whatever queued up
+    /// behind it - a further text message just dispatches detection again
+    /// without resuming yet, same as the synchronous path above.
+    fn resume_ws_request_forward(&mut self, replacement_text: Option<String>) {
+        let text = replacement_text.or_else(|| self.ws_req_text_pending.take()).unwrap_or_default();
+        let mask_key = self.next_ws_mask_key();
+        self.ws_req_forward_buf.extend_from_slice(&websocket::build_text_frame(&text, mask_key));
+        self.state = ConnectorState::Initial;
+
+        let detect = self.config.as_ref().map(|c| c.enable_input_detection).unwrap_or(false);
+        while let Some(message) = self.ws_req_pending.pop_front() {
+            if detect {
+                if let websocket::Message::Text(text) = &message {
+                    if let DataAction::StopIterationAndBuffer = self.dispatch_ws_input_text(text.clone()) {
+                        return;
+                    }
+                    continue;
+                }
+            }
+            let mask_key = self.next_ws_mask_key();
+            self.ws_req_forward_buf.extend_from_slice(&websocket::build_message_frame(&message, Some(mask_key)));
+        }
+
+        if !self.ws_req_forward_buf.is_empty() {
+            let out = std::mem::take(&mut self.ws_req_forward_buf);
+            self.set_http_request_body(0, i32::MAX as usize, &out);
+        }
+        self.resume_http_request();
+    }
+
+    /// Stop forwarding the client's blocked message upstream and close the
+    /// connection with a WebSocket close frame carrying `code`/`reason`.
+    /// This pushes the close frame via `send_http_response` right away
+    /// rather than queuing it for the next `on_http_response_body` call:
+    /// the model is commonly waiting on the client's next message (which
+    /// was just dropped), so in that case no further response bytes - and
+    /// no further response body callback - would ever arrive, and a queued
+    /// frame would sit unflushed forever. `send_http_response` is the one
+    /// host call that can deliver data and end the stream without waiting
+    /// on upstream traffic.
+    fn close_websocket(&mut self, code: u16, reason: &str) {
+        self.ws_req_pending.clear();
+        self.ws_req_forward_buf.clear();
+        self.ws_resp_pending.clear();
+        self.ws_resp_forward_buf.clear();
+        self.set_http_request_body(0, i32::MAX as usize, &[]);
+        self.set_http_response_body(0, i32::MAX as usize, &[]);
+        self.state = ConnectorState::Done;
+        self.resume_http_request();
+        self.resume_http_response();
+
+        log::warn!("[OG-WS] Closing connection: ctx={}, code={}, reason={}", self.context_id, code, reason);
+        self.send_http_response(200, vec![], Some(&websocket::build_close_frame(code, reason)));
+    }
+
+    /// Reassemble model -> client frames and run each complete text message
+    /// through output detection before forwarding it, mirroring
+    /// `handle_ws_request_body` for the opposite direction.
+    fn handle_ws_response_body(&mut self, body_size: usize) -> DataAction {
+        // A detection call for an earlier message is still in flight; keep
+        // accumulating without forwarding so frame order is preserved.
+        if self.state == ConnectorState::WaitingWsOutputResponse {
+            return DataAction::StopIterationAndBuffer;
+        }
+
+        if let Some(chunk) = self.get_http_response_body(0, body_size) {
+            self.ws_resp_buffer.extend_from_slice(&chunk);
+        }
+
+        let messages = websocket::drain_messages(&mut self.ws_resp_buffer, &mut self.ws_resp_fragment);
+        self.ws_resp_pending.extend(messages);
+
+        self.forward_ws_response_messages()
+    }
+
+    /// Drain `ws_resp_pending`, rebuilding pass-through frames into
+    /// `ws_resp_forward_buf` and pausing to dispatch detection on the first
+    /// text message, if output detection is enabled. Server -> client frames
+    /// are forwarded unmasked, per RFC 6455.
+    fn forward_ws_response_messages(&mut self) -> DataAction {
+        let detect = self.config.as_ref().map(|c| c.enable_output_detection).unwrap_or(false);
+
+        while let Some(message) = self.ws_resp_pending.pop_front() {
+            if detect {
+                if let websocket::Message::Text(text) = &message {
+                    return self.dispatch_ws_output_text(text.clone());
+                }
+            }
+            self.ws_resp_forward_buf.extend_from_slice(&websocket::build_message_frame(&message, None));
+        }
+
+        if self.ws_resp_forward_buf.is_empty() {
+            return DataAction::StopIterationAndBuffer;
+        }
+        let out = std::mem::take(&mut self.ws_resp_forward_buf);
+        self.set_http_response_body(0, i32::MAX as usize, &out);
+        DataAction::Continue
+    }
+
+    /// Dispatch output detection for one reassembled WebSocket text message,
+    /// mirroring the JSON path's `call_og_api("/v1/gateway/process-output", ...)`
+    /// but scoped to a single frame's text instead of the whole buffered
+    /// `sse_content_accum`/response body.
+    fn dispatch_ws_output_text(&mut self, text: String) -> DataAction {
+        let request_body = self.build_output_request(&text);
+        self.ws_resp_text_pending = Some(text);
+        self.retry_count = 0;
+        match self.call_og_api("/v1/gateway/process-output", &request_body) {
+            Ok(token_id) => {
+                log::warn!("[OG-WS-RESP] Output detection dispatched: ctx={}, token_id={}", self.context_id, token_id);
+                self.state = ConnectorState::WaitingWsOutputResponse;
+                DataAction::StopIterationAndBuffer
+            }
+            Err(e) => {
+                log::error!("[OG-WS-RESP] Output detection dispatch failed: ctx={}, error={:?}", self.context_id, e);
+                self.on_dispatch_error()
+            }
+        }
+    }
+
+    /// Handle OG's verdict for the in-flight WebSocket text message: forward
+    /// the (possibly anonymized/restored) text as a fresh text frame, or
+    /// close the connection on block - there's no response body left to
+    /// carry a block response over an already-upgraded connection.
+    fn handle_ws_output_response(&mut self, body: &[u8]) {
+        let response: OGOutputResponse = match serde_json::from_slice(body) {
+            Ok(r) => r,
+            Err(e) => {
+                log::error!("[OG-WS-RESP] Failed to parse output response: ctx={}, error={}", self.context_id, e);
+                self.resume_ws_response_forward(None);
+                return;
+            }
+        };
+
+        log::warn!("[OG-WS-RESP] Parsed response: ctx={}, action={}", self.context_id, response.action);
+
+        match response.action.as_str() {
+            "block" => {
+                log::warn!("[OG-WS-RESP] Action=BLOCK: closing connection with policy violation: ctx={}", self.context_id);
+                self.metrics.incr(self.metrics.blocked_total);
+                let reason = response.block_response.as_ref().map(|r| r.body.clone())
+                    .unwrap_or_else(|| "blocked by content policy".to_string());
+                self.ws_resp_text_pending = None;
+                self.close_websocket(websocket::CLOSE_POLICY_VIOLATION, &safe_truncate(&reason, 123));
+            }
+            "anonymize" => {
+                log::warn!("[OG-WS-RESP] Action=ANONYMIZE: ctx={}", self.context_id);
+                self.metrics.incr(self.metrics.anonymized_total);
+                self.resume_ws_response_forward(response.anonymized_content);
+            }
+            "restore" => {
+                log::warn!("[OG-WS-RESP] Action=RESTORE: ctx={}", self.context_id);
+                self.resume_ws_response_forward(response.restored_content);
+            }
+            _ => {
+                self.resume_ws_response_forward(None);
+            }
+        }
+    }
+
+    /// Forward the decided text (the anonymized/restored replacement, or the
+    /// original if none was given), then keep draining whatever queued up
+    /// behind it - a further text message just dispatches detection again
+    /// without resuming yet, same as the request-direction path above.
+    fn resume_ws_response_forward(&mut self, replacement_text: Option<String>) {
+        let text = replacement_text.or_else(|| self.ws_resp_text_pending.take()).unwrap_or_default();
+        self.ws_resp_forward_buf.extend_from_slice(&websocket::build_message_frame(&websocket::Message::Text(text), None));
+        self.state = ConnectorState::Initial;
+
+        let detect = self.config.as_ref().map(|c| c.enable_output_detection).unwrap_or(false);
+        while let Some(message) = self.ws_resp_pending.pop_front() {
+            if detect {
+                if let websocket::Message::Text(text) = &message {
+                    if let DataAction::StopIterationAndBuffer = self.dispatch_ws_output_text(text.clone()) {
+                        return;
+                    }
+                    continue;
+                }
+            }
+            self.ws_resp_forward_buf.extend_from_slice(&websocket::build_message_frame(&message, None));
+        }
+
+        if !self.ws_resp_forward_buf.is_empty() {
+            let out = std::mem::take(&mut self.ws_resp_forward_buf);
+            self.set_http_response_body(0, i32::MAX as usize, &out);
+        }
+        self.resume_http_response();
+    }
 }
 
 impl Context for OGConnector {
@@ -943,47 +2179,36 @@ impl Context for OGConnector {
         log::warn!("[OG-CALLBACK] on_http_call_response: ctx={}, token_id={}, num_headers={}, body_size={}, num_trailers={}, state={:?}",
             self.context_id, token_id, num_headers, body_size, num_trailers, self.state);
 
+        if let Some(started_at) = self.dispatch_started_at_ms.take() {
+            let elapsed = self.now_ms().saturating_sub(started_at);
+            log::warn!("[OG-CALLBACK] OG API latency: ctx={}, elapsed_ms={}", self.context_id, elapsed);
+            self.metrics.record_api_latency_ms(elapsed);
+        }
+
         // Check for HTTP status code first
         let status_ok = if let Some(status) = self.get_http_call_response_header(":status") {
             log::warn!("[OG-CALLBACK] HTTP response status: ctx={}, status={}", self.context_id, status);
             if status != "200" {
                 log::error!("[OG-CALLBACK] Non-200 response from OG: ctx={}, status={}", self.context_id, status);
-                // On timeout or error, resume original request/response without modification
-                match self.state {
-                    ConnectorState::WaitingInputResponse => {
-                        log::warn!("[OG-CALLBACK] OG API error, resuming original request: ctx={}", self.context_id);
-                        self.state = ConnectorState::Initial;
-                        self.resume_http_request();
-                    }
-                    ConnectorState::WaitingOutputResponse => {
-                        // If we have a pending proxy response, return it directly
-                        if let Some(proxy_resp) = self.pending_proxy_response.take() {
-                            log::warn!("[OG-CALLBACK] OG API error, returning pending proxy response: ctx={}", self.context_id);
-                            self.send_local_response(
-                                proxy_resp.code as u32,
-                                &proxy_resp.content_type,
-                                proxy_resp.body.as_bytes(),
-                            );
-                        } else {
-                            log::warn!("[OG-CALLBACK] OG API error, resuming original response: ctx={}", self.context_id);
-                            self.state = ConnectorState::Done;
-                            self.resume_http_response();
-                        }
-                    }
-                    _ => {}
-                }
+                self.record_backend_result(false);
+                self.handle_og_api_failure();
                 return;
             }
             true
         } else {
-            log::error!("[OG-CALLBACK] No status header in response: ctx={}", self.context_id);
+            // No status header at all is how a dispatch timeout surfaces.
+            log::error!("[OG-CALLBACK] No status header in response (likely timeout): ctx={}", self.context_id);
             false
         };
 
         if !status_ok {
+            self.record_backend_result(false);
+            self.handle_og_api_failure();
             return;
         }
 
+        self.record_backend_result(true);
+
         let body = match self.get_http_call_response_body(0, body_size) {
             Some(b) => {
                 log::warn!("[OG-CALLBACK] Got response body: ctx={}, len={}", self.context_id, b.len());
@@ -999,6 +2224,8 @@ impl Context for OGConnector {
         let body_preview = String::from_utf8_lossy(&body);
         log::warn!("[OG-CALLBACK] Response body preview: ctx={}, body={}", self.context_id, safe_truncate(&body_preview, 300));
 
+        self.maybe_cache_decision(&body);
+
         match self.state {
             ConnectorState::WaitingInputResponse => {
                 log::warn!("[OG-CALLBACK] Processing input response: ctx={}", self.context_id);
@@ -1007,14 +2234,26 @@ impl Context for OGConnector {
             }
             ConnectorState::WaitingOutputResponse => {
                 log::warn!("[OG-CALLBACK] Processing output response: ctx={}", self.context_id);
-                self.state = ConnectorState::Done;
+                // `handle_output_response` sets the follow-up state itself:
+                // `Done` once the response is fully resolved, or back to
+                // `Initial` for a mid-stream SSE window so more chunks can
+                // still arrive.
                 self.handle_output_response(&body);
             }
+            ConnectorState::WaitingWsInputResponse => {
+                log::warn!("[OG-CALLBACK] Processing WebSocket input response: ctx={}", self.context_id);
+                self.handle_ws_input_response(&body);
+            }
+            ConnectorState::WaitingWsOutputResponse => {
+                log::warn!("[OG-CALLBACK] Processing WebSocket output response: ctx={}", self.context_id);
+                self.handle_ws_output_response(&body);
+            }
             _ => {
                 log::error!("[OG-CALLBACK] Unexpected state: ctx={}, state={:?}", self.context_id, self.state);
             }
         }
     }
+
 }
 
 impl HttpContext for OGConnector {
@@ -1032,12 +2271,25 @@ impl HttpContext for OGConnector {
             log::warn!("[OG-REQ-HDR] BYPASS: Detected bypass token, skipping detection: ctx={}, token_len={}",
                 self.context_id, bypass_token.len());
             self.bypassed = true;
+            self.metrics.incr(self.metrics.bypassed_total);
             // Remove the bypass token header before forwarding to upstream
             self.set_http_request_header("X-OG-Bypass-Token", None);
             // Continue without detection - pass through directly
             return HeaderAction::Continue;
         }
 
+        // Realtime/voice endpoints negotiate a WebSocket upgrade instead of
+        // a plain request/response exchange; the buffered-JSON body pipeline
+        // below doesn't apply once the connection switches to tunneled
+        // frames, so detect it here and branch the body callbacks.
+        let upgrade_hdr = self.get_http_request_header("upgrade").unwrap_or_default();
+        let connection_hdr = self.get_http_request_header("connection").unwrap_or_default();
+        self.is_websocket = upgrade_hdr.eq_ignore_ascii_case("websocket")
+            && connection_hdr.to_ascii_lowercase().contains("upgrade");
+        if self.is_websocket {
+            log::warn!("[OG-REQ-HDR] WebSocket upgrade detected, switching to frame-streaming mode: ctx={}", self.context_id);
+        }
+
         // Extract consumer ID from gateway (e.g., x-mse-consumer from Higress key-auth plugin)
         // This is used for automatic application discovery in OG
         // Try both lowercase and mixed-case header names for compatibility
@@ -1048,6 +2300,34 @@ impl HttpContext for OGConnector {
                 self.context_id, consumer);
         }
 
+        // `Expect: 100-continue` means the client is waiting on us before it
+        // streams a (possibly large) body. proxy-wasm has no host call to
+        // emit the interim 100 itself - Envoy already forwards that upstream
+        // on its own for a passthrough request - but the header is still a
+        // useful cue to reject an oversized or malformed request up front
+        // using its declared `content-length`, instead of buffering the
+        // whole body first only to discard it in `apply_failure_mode`-style
+        // checks later.
+        let max_request_body = self.config.as_ref().map(|c| c.max_request_body_bytes).unwrap_or(0);
+        if max_request_body > 0
+            && self.get_http_request_header("expect").map(|v| v.eq_ignore_ascii_case("100-continue")).unwrap_or(false)
+        {
+            match self.get_http_request_header("content-length").and_then(|v| v.parse::<usize>().ok()) {
+                Some(len) if len > max_request_body => {
+                    log::warn!("[OG-REQ-HDR] Rejecting oversized Expect:100-continue request: ctx={}, declared_len={}, max={}",
+                        self.context_id, len, max_request_body);
+                    self.send_local_response(413, "application/json", br#"{"error":"request body too large"}"#);
+                    return HeaderAction::StopIteration;
+                }
+                None => {
+                    log::warn!("[OG-REQ-HDR] Expect:100-continue without content-length, rejecting: ctx={}", self.context_id);
+                    self.send_local_response(417, "application/json", br#"{"error":"missing content-length"}"#);
+                    return HeaderAction::StopIteration;
+                }
+                _ => {}
+            }
+        }
+
         let path = self.get_http_request_header(":path").unwrap_or_default();
         let method = self.get_http_request_header(":method").unwrap_or_default();
         let authority = self.get_http_request_header(":authority").unwrap_or_default();
@@ -1055,6 +2335,54 @@ impl HttpContext for OGConnector {
         log::warn!("[OG-REQ-HDR] Request: ctx={}, method={}, path={}, authority={}",
             self.context_id, method, path, authority);
 
+        // An upgraded connection has no content-length and its body is a
+        // long-lived tunnel, not a single buffered payload; let the
+        // handshake through and let `on_http_request_body` stream frames.
+        if self.is_websocket {
+            return HeaderAction::Continue;
+        }
+
+        // Decide whether to decode a compressed request body up front, same
+        // as `on_http_response_headers` does for the response direction -
+        // the header can't be un-stripped once the body turns out malformed.
+        let input_detection_enabled = self.config.as_ref().map(|c| c.enable_input_detection).unwrap_or(false);
+        let encoding_min_bytes = self.config.as_ref().map(|c| c.encoding_min_bytes).unwrap_or(0);
+        if input_detection_enabled {
+            if let Some(raw_encoding) = self.get_http_request_header("content-encoding") {
+                let codec = raw_encoding.trim().to_ascii_lowercase();
+                let declared_len = self
+                    .get_http_request_header("content-length")
+                    .and_then(|v| v.parse::<usize>().ok());
+                let worth_decoding = declared_len.map(|len| len >= encoding_min_bytes).unwrap_or(true);
+                if worth_decoding && encoding::is_supported(&codec) {
+                    log::warn!("[OG-REQ-HDR] Stripping content-encoding={} to decode for inspection: ctx={}", codec, self.context_id);
+                    self.request_content_encoding = codec;
+                    self.set_http_request_header("content-encoding", None);
+                }
+            }
+        }
+
+        // Bail out on an oversized body before buffering any of it, using
+        // the declared content-length, when `block_on_oversized` is
+        // configured - the `max_inspect_bytes` check in `on_http_request_body`
+        // runs only after the whole body is already sitting in the WASM
+        // sandbox, which is exactly the hazard `max_inspect_bytes` exists to
+        // bound. A request without `content-length` (e.g. chunked transfer
+        // encoding) isn't caught here and falls through to that later check.
+        let max_inspect_bytes = self.config.as_ref().map(|c| c.max_inspect_bytes).unwrap_or(0);
+        let block_on_oversized = self.config.as_ref().map(|c| c.block_on_oversized).unwrap_or(false);
+        if input_detection_enabled && max_inspect_bytes > 0 && block_on_oversized {
+            if let Some(len) = self.get_http_request_header("content-length").and_then(|v| v.parse::<usize>().ok()) {
+                if len > max_inspect_bytes {
+                    log::warn!("[OG-REQ-HDR] Declared content-length exceeds max_inspect_bytes, blocking before buffering: ctx={}, declared_len={}, max={}",
+                        self.context_id, len, max_inspect_bytes);
+                    let status_code = self.config.as_ref().map(|c| c.fail_closed_status_code).unwrap_or_else(default_fail_closed_status);
+                    self.send_local_response(status_code, "application/json", br#"{"error":"request body too large to inspect"}"#);
+                    return HeaderAction::StopIteration;
+                }
+            }
+        }
+
         // Remove Content-Length header as we may modify the body
         self.set_http_request_header("content-length", None);
 
@@ -1066,6 +2394,10 @@ impl HttpContext for OGConnector {
         log::warn!("[OG-REQ-BODY] on_http_request_body: ctx={}, body_size={}, end_of_stream={}, state={:?}, bypassed={}",
             self.context_id, body_size, end_of_stream, self.state, self.bypassed);
 
+        if self.is_websocket {
+            return self.handle_ws_request_body(body_size);
+        }
+
         // Skip input detection if this request was bypassed (private model from OG)
         if self.bypassed {
             log::warn!("[OG-REQ-BODY] BYPASS: Skipping input detection for bypassed request: ctx={}", self.context_id);
@@ -1086,6 +2418,26 @@ impl HttpContext for OGConnector {
             log::error!("[OG-REQ-BODY] Failed to get request body: ctx={}, body_size={}", self.context_id, body_size);
         }
 
+        // The header callback already committed to decoding (and stripped
+        // content-encoding accordingly) if this is non-empty. A decode
+        // failure fails open by logging and leaving the body as-is: it
+        // won't parse as JSON below, so detection is skipped rather than
+        // blocking on an undecodable request.
+        if !self.request_content_encoding.is_empty() {
+            match encoding::decode(&self.request_body, &self.request_content_encoding) {
+                Some(plain) => {
+                    log::warn!("[OG-ENCODING] Decoded {} request body for inspection: ctx={}, compressed_len={}, plain_len={}",
+                        self.request_content_encoding, self.context_id, self.request_body.len(), plain.len());
+                    self.set_http_request_body(0, i32::MAX as usize, &plain);
+                    self.request_body = plain;
+                }
+                None => {
+                    log::error!("[OG-ENCODING] Failed to decode {} request body, content-encoding header was already stripped: ctx={}",
+                        self.request_content_encoding, self.context_id);
+                }
+            }
+        }
+
         if self.config.is_none() {
             log::warn!("[OG-REQ-BODY] No config, passing through: ctx={}", self.context_id);
             return DataAction::Continue;
@@ -1102,6 +2454,16 @@ impl HttpContext for OGConnector {
             return DataAction::Continue;
         }
 
+        let max_inspect_bytes = config.max_inspect_bytes;
+        let block_on_oversized = config.block_on_oversized;
+        let fail_closed_status_code = config.fail_closed_status_code;
+        if max_inspect_bytes > 0 && block_on_oversized && self.request_body.len() > max_inspect_bytes {
+            log::warn!("[OG-REQ-BODY] Body exceeds max_inspect_bytes, blocking: ctx={}, len={}, max={}",
+                self.context_id, self.request_body.len(), max_inspect_bytes);
+            self.send_local_response(fail_closed_status_code, "application/json", br#"{"error":"request body too large to inspect"}"#);
+            return DataAction::Continue;
+        }
+
         let messages = match self.parse_messages(&self.request_body) {
             Some(m) => m,
             None => {
@@ -1119,9 +2481,21 @@ impl HttpContext for OGConnector {
         self.is_streaming = self.check_streaming(&self.request_body);
         log::warn!("[OG-REQ-BODY] Parsed {} messages, streaming={}: ctx={}", messages.len(), self.is_streaming, self.context_id);
 
+        if config.cache_ttl_ms > 0 {
+            let canonical = serde_json::to_string(&messages).unwrap_or_default();
+            let key = cache::cache_key(&config.application_id, "input", &canonical);
+            if let Some(cached) = cache::get(self, &key, config.cache_ttl_ms) {
+                log::warn!("[OG-CACHE] Input cache HIT: ctx={}, key={}", self.context_id, key);
+                return self.apply_cached_input_decision(&cached);
+            }
+            log::warn!("[OG-CACHE] Input cache MISS: ctx={}, key={}", self.context_id, key);
+            self.pending_cache_key = Some(key);
+        }
+
         let request_body = self.build_input_request(messages);
         log::warn!("[OG-REQ-BODY] Built input request, calling OG API: ctx={}", self.context_id);
 
+        self.retry_count = 0;
         match self.call_og_api("/v1/gateway/process-input", &request_body) {
             Ok(token_id) => {
                 log::warn!("[OG-REQ-BODY] API call dispatched: ctx={}, token_id={}, state -> WaitingInputResponse",
@@ -1131,8 +2505,8 @@ impl HttpContext for OGConnector {
                 DataAction::StopIterationAndBuffer
             }
             Err(e) => {
-                log::error!("[OG-REQ-BODY] API call FAILED: ctx={}, error={:?}, passing through", self.context_id, e);
-                DataAction::Continue
+                log::error!("[OG-REQ-BODY] API call FAILED: ctx={}, error={:?}", self.context_id, e);
+                self.on_dispatch_error()
             }
         }
     }
@@ -1147,6 +2521,60 @@ impl HttpContext for OGConnector {
             return HeaderAction::Continue;
         }
 
+        // Detect SSE streaming completions so the body callback can switch
+        // into the incremental accumulator instead of the single-JSON path
+        if let Some(content_type) = self.get_http_response_header("content-type") {
+            self.is_sse_response = content_type.contains("text/event-stream");
+            if self.is_sse_response {
+                log::warn!("[OG-RSP-HDR] Detected SSE streaming response: ctx={}", self.context_id);
+            }
+        }
+
+        // Decide whether to decode a compressed body up front, since the
+        // header can't be un-stripped once forwarded. Only worth it for the
+        // single-JSON path (an SSE stream can't be decoded chunk-by-chunk
+        // with a stateful codec) and only when there's something to detect.
+        let output_detection_enabled = self.config.as_ref().map(|c| c.enable_output_detection).unwrap_or(false);
+        let encoding_min_bytes = self.config.as_ref().map(|c| c.encoding_min_bytes).unwrap_or(0);
+        if !self.is_sse_response && output_detection_enabled {
+            if let Some(raw_encoding) = self.get_http_response_header("content-encoding") {
+                let codec = raw_encoding.trim().to_ascii_lowercase();
+                let declared_len = self
+                    .get_http_response_header("content-length")
+                    .and_then(|v| v.parse::<usize>().ok());
+                let worth_decoding = declared_len.map(|len| len >= encoding_min_bytes).unwrap_or(true);
+                if worth_decoding && encoding::is_supported(&codec) {
+                    log::warn!("[OG-RSP-HDR] Stripping content-encoding={} to decode for inspection: ctx={}", codec, self.context_id);
+                    self.response_content_encoding = codec;
+                    self.set_http_response_header("content-encoding", None);
+                }
+            }
+        }
+
+        // The 101 response to an upgrade has no content-length of its own.
+        if self.is_websocket {
+            return HeaderAction::Continue;
+        }
+
+        // Bail out on an oversized response before buffering any of it,
+        // mirroring the request-side check in `on_http_request_headers`.
+        // Doesn't apply to SSE responses, which are inspected in bounded
+        // windows already (`handle_sse_response_body`) rather than buffered
+        // whole.
+        let max_inspect_bytes = self.config.as_ref().map(|c| c.max_inspect_bytes).unwrap_or(0);
+        let block_on_oversized = self.config.as_ref().map(|c| c.block_on_oversized).unwrap_or(false);
+        if !self.is_sse_response && output_detection_enabled && max_inspect_bytes > 0 && block_on_oversized {
+            if let Some(len) = self.get_http_response_header("content-length").and_then(|v| v.parse::<usize>().ok()) {
+                if len > max_inspect_bytes {
+                    log::warn!("[OG-RSP-HDR] Declared content-length exceeds max_inspect_bytes, blocking before buffering: ctx={}, declared_len={}, max={}",
+                        self.context_id, len, max_inspect_bytes);
+                    let status_code = self.config.as_ref().map(|c| c.fail_closed_status_code).unwrap_or_else(default_fail_closed_status);
+                    self.send_local_response(status_code, "application/json", br#"{"error":"response body too large to inspect"}"#);
+                    return HeaderAction::StopIteration;
+                }
+            }
+        }
+
         // Remove Content-Length as we may modify the response
         self.set_http_response_header("content-length", None);
         HeaderAction::Continue
@@ -1156,6 +2584,10 @@ impl HttpContext for OGConnector {
         log::warn!("[OG-RSP-BODY] on_http_response_body: ctx={}, body_size={}, end_of_stream={}, state={:?}, bypassed={}, response_sent={}",
             self.context_id, body_size, end_of_stream, self.state, self.bypassed, self.response_sent);
 
+        if self.is_websocket {
+            return self.handle_ws_response_body(body_size);
+        }
+
         // If we already sent a local response, skip processing
         if self.response_sent {
             log::warn!("[OG-RSP-BODY] Response already sent, skipping: ctx={}", self.context_id);
@@ -1164,6 +2596,17 @@ impl HttpContext for OGConnector {
 
         // If we already sent a block/replace response, don't process further
         if self.state == ConnectorState::Done {
+            if self.is_sse_response {
+                // The stream was already terminated with a synthetic block
+                // frame + `[DONE]` from `handle_output_response`; any bytes
+                // still arriving from upstream are post-block content that
+                // must never reach the client, unlike the buffered-JSON
+                // path below where `Done` only happens once the whole
+                // response has already been decided.
+                log::warn!("[OG-RSP-BODY] State is Done (SSE already terminated), dropping remainder: ctx={}", self.context_id);
+                self.set_http_response_body(0, i32::MAX as usize, &[]);
+                return DataAction::Continue;
+            }
             log::warn!("[OG-RSP-BODY] State is Done, passing through: ctx={}", self.context_id);
             return DataAction::Continue;
         }
@@ -1174,6 +2617,20 @@ impl HttpContext for OGConnector {
             return DataAction::Continue;
         }
 
+        let output_detection_enabled = match &self.config {
+            Some(c) => c.enable_output_detection,
+            None => {
+                log::warn!("[OG-RSP-BODY] No config, passing through: ctx={}", self.context_id);
+                return DataAction::Continue;
+            }
+        };
+
+        if self.is_sse_response && output_detection_enabled {
+            return self.handle_sse_response_body(body_size, end_of_stream);
+        }
+
+        let config = self.config.as_ref().unwrap();
+
         // Buffer until we receive end_of_stream
         if !end_of_stream {
             log::warn!("[OG-RSP-BODY] Buffering, not end of stream: ctx={}", self.context_id);
@@ -1188,13 +2645,22 @@ impl HttpContext for OGConnector {
             log::error!("[OG-RSP-BODY] Failed to get response body: ctx={}, body_size={}", self.context_id, body_size);
         }
 
-        let config = match &self.config {
-            Some(c) => c,
-            None => {
-                log::warn!("[OG-RSP-BODY] No config, passing through: ctx={}", self.context_id);
-                return DataAction::Continue;
+        // The header callback already committed to decoding (and stripped
+        // content-encoding accordingly) if this is non-empty.
+        if !self.response_content_encoding.is_empty() {
+            match encoding::decode(&self.response_body, &self.response_content_encoding) {
+                Some(plain) => {
+                    log::warn!("[OG-ENCODING] Decoded {} response body for inspection: ctx={}, compressed_len={}, plain_len={}",
+                        self.response_content_encoding, self.context_id, self.response_body.len(), plain.len());
+                    self.set_http_response_body(0, i32::MAX as usize, &plain);
+                    self.response_body = plain;
+                }
+                None => {
+                    log::error!("[OG-ENCODING] Failed to decode {} response body, content-encoding header was already stripped: ctx={}",
+                        self.response_content_encoding, self.context_id);
+                }
             }
-        };
+        }
 
         // Skip output detection if disabled and no session (no anonymization was done)
         if !config.enable_output_detection && self.session_id.is_none() && self.restore_mapping.is_none() {
@@ -1202,6 +2668,16 @@ impl HttpContext for OGConnector {
             return DataAction::Continue;
         }
 
+        let max_inspect_bytes = config.max_inspect_bytes;
+        let block_on_oversized = config.block_on_oversized;
+        let fail_closed_status_code = config.fail_closed_status_code;
+        if max_inspect_bytes > 0 && block_on_oversized && self.response_body.len() > max_inspect_bytes {
+            log::warn!("[OG-RSP-BODY] Body exceeds max_inspect_bytes, blocking: ctx={}, len={}, max={}",
+                self.context_id, self.response_body.len(), max_inspect_bytes);
+            self.send_local_response(fail_closed_status_code, "application/json", br#"{"error":"response body too large to inspect"}"#);
+            return DataAction::Continue;
+        }
+
         let content = match self.extract_response_content() {
             Some(c) => c,
             None => {
@@ -1213,10 +2689,21 @@ impl HttpContext for OGConnector {
             }
         };
 
+        if config.cache_ttl_ms > 0 {
+            let key = cache::cache_key(&config.application_id, "output", &content);
+            if let Some(cached) = cache::get(self, &key, config.cache_ttl_ms) {
+                log::warn!("[OG-CACHE] Output cache HIT: ctx={}, key={}", self.context_id, key);
+                return self.apply_cached_output_decision(&cached);
+            }
+            log::warn!("[OG-CACHE] Output cache MISS: ctx={}, key={}", self.context_id, key);
+            self.pending_cache_key = Some(key);
+        }
+
         log::warn!("[OG-RSP-BODY] Calling process-output: ctx={}, content_len={}, session_id={:?}, restore_mapping_count={:?}",
             self.context_id, content.len(), self.session_id, self.restore_mapping.as_ref().map(|m| m.len()));
         let request_body = self.build_output_request(&content);
 
+        self.retry_count = 0;
         match self.call_og_api("/v1/gateway/process-output", &request_body) {
             Ok(token_id) => {
                 log::warn!("[OG-RSP-BODY] API call dispatched: ctx={}, token_id={}, state -> WaitingOutputResponse",
@@ -1225,9 +2712,77 @@ impl HttpContext for OGConnector {
                 DataAction::StopIterationAndBuffer
             }
             Err(e) => {
-                log::error!("[OG-RSP-BODY] API call FAILED: ctx={}, error={:?}, passing through", self.context_id, e);
-                DataAction::Continue
+                log::error!("[OG-RSP-BODY] API call FAILED: ctx={}, error={:?}", self.context_id, e);
+                self.on_dispatch_error()
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safe_truncate_leaves_short_strings_untouched() {
+        assert_eq!(safe_truncate("hello", 10), "hello");
+    }
+
+    #[test]
+    fn test_safe_truncate_cuts_on_char_not_byte_boundary() {
+        // Each "é" is two bytes; truncating at a byte offset would panic.
+        let truncated = safe_truncate("éééé", 2);
+        assert_eq!(truncated, "éé...");
+    }
+
+    #[test]
+    fn test_floor_char_boundary_steps_back_off_a_multibyte_char() {
+        let s = "aé"; // 'a' = 1 byte, 'é' = 2 bytes, so byte index 2 is mid-char
+        assert_eq!(floor_char_boundary(s, 2), 1);
+        assert_eq!(floor_char_boundary(s, 0), 0);
+        assert_eq!(floor_char_boundary(s, 100), s.len());
+    }
+
+    #[test]
+    fn test_ceil_char_boundary_steps_forward_off_a_multibyte_char() {
+        let s = "aé";
+        assert_eq!(ceil_char_boundary(s, 2), 3);
+        assert_eq!(ceil_char_boundary(s, 3), 3);
+        assert_eq!(ceil_char_boundary(s, 100), s.len());
+    }
+
+    #[test]
+    fn test_truncate_for_inspection_passes_short_text_through_unmarked() {
+        let (text, truncated) = truncate_for_inspection("hello", 1_000);
+        assert_eq!(text, "hello");
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn test_truncate_for_inspection_disabled_when_limit_is_zero() {
+        let (text, truncated) = truncate_for_inspection(&"x".repeat(10_000), 0);
+        assert_eq!(text.len(), 10_000);
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn test_truncate_for_inspection_keeps_head_and_tail_halves() {
+        let text = "A".repeat(50) + &"B".repeat(50);
+        let (out, truncated) = truncate_for_inspection(&text, 40);
+        assert!(truncated);
+        assert!(out.starts_with(&"A".repeat(20)));
+        assert!(out.ends_with(&"B".repeat(20)));
+        assert!(out.contains("...[truncated]..."));
+    }
+
+    #[test]
+    fn test_truncate_for_inspection_does_not_split_a_multibyte_char() {
+        // 29 bytes with a 10-byte budget: the tail cut (byte 24) falls inside
+        // an 'é', so it must land on either side of it rather than inside
+        // its two bytes.
+        let text = format!("{}{}", "a".repeat(9), "é".repeat(10));
+        let (out, truncated) = truncate_for_inspection(&text, 10);
+        assert!(truncated);
+        assert!(out.is_char_boundary(out.find("...[truncated]...").unwrap()));
+    }
+}
@@ -0,0 +1,69 @@
+/*!
+Prometheus-scrapable counters/histogram for detection outcomes and OG API
+latency, exposed through Envoy's existing stats endpoint via the proxy-wasm
+metric host calls.
+
+Metric ids are defined once per VM in `OGConnectorRoot::on_configure` and
+copied (they're just `u32` handles) into every `OGConnector` so all HTTP
+contexts increment the same underlying Envoy stats.
+*/
+
+use proxy_wasm::hostcalls;
+use proxy_wasm::types::MetricType;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Metrics {
+    pub(crate) requests_input_total: u32,
+    pub(crate) requests_output_total: u32,
+    pub(crate) blocked_total: u32,
+    pub(crate) replaced_total: u32,
+    pub(crate) anonymized_total: u32,
+    pub(crate) bypassed_total: u32,
+    pub(crate) api_latency_ms: u32,
+}
+
+impl Metrics {
+    /// Register every metric with the host. Called once from
+    /// `on_configure`; failures just leave the handle at 0 (increments
+    /// against it are then a harmless no-op on the host side).
+    pub(crate) fn define() -> Self {
+        Metrics {
+            // Envoy stat names don't carry structured labels through this
+            // API, so the `direction` dimension from the request is
+            // encoded as a name suffix instead of a tag.
+            requests_input_total: define_counter("og_requests_total_input"),
+            requests_output_total: define_counter("og_requests_total_output"),
+            blocked_total: define_counter("og_blocked_total"),
+            replaced_total: define_counter("og_replaced_total"),
+            anonymized_total: define_counter("og_anonymized_total"),
+            bypassed_total: define_counter("og_bypassed_total"),
+            api_latency_ms: define_histogram("og_api_latency_ms"),
+        }
+    }
+
+    pub(crate) fn incr(&self, metric_id: u32) {
+        if let Err(e) = hostcalls::increment_metric(metric_id, 1) {
+            log::warn!("[OG-METRICS] increment_metric failed: metric_id={}, error={:?}", metric_id, e);
+        }
+    }
+
+    pub(crate) fn record_api_latency_ms(&self, elapsed_ms: u64) {
+        if let Err(e) = hostcalls::record_metric(self.api_latency_ms, elapsed_ms) {
+            log::warn!("[OG-METRICS] record_metric failed: metric_id={}, error={:?}", self.api_latency_ms, e);
+        }
+    }
+}
+
+fn define_counter(name: &str) -> u32 {
+    hostcalls::define_metric(MetricType::Counter, name).unwrap_or_else(|e| {
+        log::error!("[OG-METRICS] define_metric failed: name={}, error={:?}", name, e);
+        0
+    })
+}
+
+fn define_histogram(name: &str) -> u32 {
+    hostcalls::define_metric(MetricType::Histogram, name).unwrap_or_else(|e| {
+        log::error!("[OG-METRICS] define_metric failed: name={}, error={:?}", name, e);
+        0
+    })
+}
@@ -0,0 +1,344 @@
+/*!
+Helpers for parsing and re-emitting Server-Sent Events (SSE) frames used by
+OpenAI-style streaming chat completions (`text/event-stream`).
+
+proxy-wasm delivers the response body to `on_http_response_body` across
+multiple pauses, and a single `data: {...}` event can be split across two
+of those calls. The parser here is line/byte oriented and designed to be
+fed repeatedly with whatever has accumulated so far, returning only the
+complete events and leaving the trailing partial bytes for the next call.
+*/
+
+use crate::formats::ResponseFormat;
+
+/// One parsed `data:` line from an SSE event, before any detection-driven
+/// rewrite has been applied.
+#[derive(Debug, Clone)]
+pub(crate) struct SseFrame {
+    /// Raw payload after the `data: ` prefix, e.g. `{"choices":[...]}` or `[DONE]`.
+    pub raw_data: String,
+}
+
+impl SseFrame {
+    pub(crate) fn is_done(&self) -> bool {
+        self.raw_data.trim() == "[DONE]"
+    }
+
+    /// Extract this frame's streaming delta text using `format`'s
+    /// provider-specific rule (see [`ResponseFormat::extract_delta_text`]),
+    /// e.g. OpenAI's `choices[0].delta.content` or Anthropic's
+    /// `content_block_delta` events.
+    pub(crate) fn delta_content(&self, format: &dyn ResponseFormat) -> Option<String> {
+        if self.is_done() {
+            return None;
+        }
+        let json: serde_json::Value = serde_json::from_str(&self.raw_data).ok()?;
+        format.extract_delta_text(&json)
+    }
+}
+
+/// Split `buffer` into complete `\n\n`-delimited SSE events, leaving any
+/// trailing partial event in `buffer` for the next call. Lines that aren't
+/// `data: ...` (e.g. blank keep-alives, `event:`/`id:` lines) are ignored.
+pub(crate) fn drain_complete_frames(buffer: &mut Vec<u8>) -> Vec<SseFrame> {
+    let mut frames = Vec::new();
+
+    let mut search_from = 0;
+    let mut last_split_end = 0;
+    while let Some(rel_pos) = find_subslice(&buffer[search_from..], b"\n\n") {
+        let split_end = search_from + rel_pos + 2;
+        let event_bytes = &buffer[last_split_end..split_end];
+        if let Some(frame) = parse_event(event_bytes) {
+            frames.push(frame);
+        }
+        last_split_end = split_end;
+        search_from = split_end;
+    }
+
+    if last_split_end > 0 {
+        buffer.drain(0..last_split_end);
+    }
+
+    frames
+}
+
+fn parse_event(event_bytes: &[u8]) -> Option<SseFrame> {
+    let text = String::from_utf8_lossy(event_bytes);
+    for line in text.lines() {
+        if let Some(data) = line.strip_prefix("data:") {
+            return Some(SseFrame {
+                raw_data: data.trim_start().to_string(),
+            });
+        }
+    }
+    None
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Render a single anonymized/restored text as one streaming delta chunk
+/// followed by the terminal `[DONE]` marker. Used for the fully-buffered
+/// proxy-response path, which has no original per-frame structure to
+/// preserve (OG already collapsed the whole response into one string).
+pub(crate) fn render_content_event(content: &str) -> Vec<u8> {
+    let chunk = serde_json::json!({
+        "object": "chat.completion.chunk",
+        "choices": [{
+            "index": 0,
+            "delta": { "content": content },
+            "finish_reason": serde_json::Value::Null,
+        }],
+    });
+    let mut out = format!("data: {}\n\n", chunk).into_bytes();
+    out.extend_from_slice(b"data: [DONE]\n\n");
+    out
+}
+
+/// One SSE frame queued since the last window flush, in original arrival
+/// order, carrying whatever the upstream actually sent it with.
+#[derive(Debug, Clone)]
+pub(crate) enum PendingFrame {
+    /// Carried delta text that was folded into the detection window's
+    /// accumulated content.
+    Text(String),
+    /// No delta text to inspect (tool-call/function-call arguments, a
+    /// second/third `n>1` choice with nothing in `delta.content`,
+    /// keep-alives, ...) - always forwarded unmodified.
+    Raw(String),
+}
+
+/// Forward every queued frame exactly as the upstream sent it - the "pass"
+/// action (the overwhelming majority of windows), and the fallback when a
+/// replacement can't be trusted. Unlike [`render_content_event`], this never
+/// regenerates a frame: `id`/`model`/`created`/`usage`/`finish_reason`,
+/// other choices, and tool-call deltas all reach the client untouched.
+pub(crate) fn render_passthrough(frames: &[PendingFrame], is_final: bool) -> Vec<u8> {
+    let mut out = Vec::new();
+    for frame in frames {
+        let raw = match frame {
+            PendingFrame::Text(raw) | PendingFrame::Raw(raw) => raw,
+        };
+        out.extend_from_slice(format!("data: {}\n\n", raw).as_bytes());
+    }
+    if is_final {
+        out.extend_from_slice(b"data: [DONE]\n\n");
+    }
+    out
+}
+
+/// Rewrite the queued frames with OG's anonymized/restored replacement text,
+/// via `format`'s provider-specific [`ResponseFormat::render_delta_event`],
+/// rather than synthesizing a new object. OG returns one replacement string
+/// per window, not one per original frame, so the last text-bearing frame
+/// carries the full replacement and every earlier one in the window is
+/// emptied out - the net text the client sees is still exactly `replacement`,
+/// while each frame keeps its own `id`/`model`/`usage`/other fields. Frames
+/// with no delta text (tool calls, other choices, ...) pass through
+/// unmodified, same as [`render_passthrough`].
+pub(crate) fn render_rewritten(
+    frames: &[PendingFrame],
+    replacement: &str,
+    format: &dyn ResponseFormat,
+    is_final: bool,
+) -> Vec<u8> {
+    let last_text_idx = frames.iter().rposition(|f| matches!(f, PendingFrame::Text(_)));
+    let mut out = Vec::new();
+    for (i, frame) in frames.iter().enumerate() {
+        match frame {
+            PendingFrame::Raw(raw) => {
+                out.extend_from_slice(format!("data: {}\n\n", raw).as_bytes());
+            }
+            PendingFrame::Text(raw) => {
+                let text = if Some(i) == last_text_idx { replacement } else { "" };
+                match serde_json::from_str::<serde_json::Value>(raw) {
+                    Ok(event) => {
+                        let rendered = format.render_delta_event(&event, text);
+                        out.extend_from_slice(format!("data: {}\n\n", rendered).as_bytes());
+                    }
+                    Err(_) => out.extend_from_slice(format!("data: {}\n\n", raw).as_bytes()),
+                }
+            }
+        }
+    }
+    if is_final {
+        out.extend_from_slice(b"data: [DONE]\n\n");
+    }
+    out
+}
+
+/// Render the bare terminal marker, for when the last window flush carried
+/// no new content but the stream still needs to be closed out.
+pub(crate) fn render_done() -> Vec<u8> {
+    b"data: [DONE]\n\n".to_vec()
+}
+
+/// Render a terminating SSE error event used when OG blocks a streaming
+/// response mid-flight, followed by `[DONE]` so downstream SSE clients
+/// close cleanly instead of hanging on an unterminated stream.
+pub(crate) fn render_block_event(message: &str) -> Vec<u8> {
+    let chunk = serde_json::json!({
+        "object": "chat.completion.chunk",
+        "choices": [{
+            "index": 0,
+            "delta": { "content": message },
+            "finish_reason": "content_filter",
+        }],
+    });
+    let mut out = format!("data: {}\n\n", chunk).into_bytes();
+    out.extend_from_slice(b"data: [DONE]\n\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats;
+
+    fn openai_format() -> Box<dyn ResponseFormat> {
+        formats::adapter_for(formats::Provider::OpenAi)
+    }
+
+    #[test]
+    fn test_drain_complete_frames_single_event() {
+        let mut buffer = b"data: {\"choices\":[{\"delta\":{\"content\":\"hi\"}}]}\n\n".to_vec();
+        let frames = drain_complete_frames(&mut buffer);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].delta_content(openai_format().as_ref()), Some("hi".to_string()));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_drain_complete_frames_leaves_partial_trailing_event() {
+        let mut buffer = b"data: {\"choices\":[{\"delta\":{\"content\":\"a\"}}]}\n\ndata: {\"choic".to_vec();
+        let frames = drain_complete_frames(&mut buffer);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].delta_content(openai_format().as_ref()), Some("a".to_string()));
+        // The partial second event is left in the buffer for the next call.
+        assert_eq!(buffer, b"data: {\"choic".to_vec());
+    }
+
+    #[test]
+    fn test_drain_complete_frames_fed_across_two_calls() {
+        let mut buffer = b"data: {\"choic".to_vec();
+        assert!(drain_complete_frames(&mut buffer).is_empty());
+
+        buffer.extend_from_slice(b"es\":[{\"delta\":{\"content\":\"ok\"}}]}\n\n");
+        let frames = drain_complete_frames(&mut buffer);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].delta_content(openai_format().as_ref()), Some("ok".to_string()));
+    }
+
+    #[test]
+    fn test_drain_complete_frames_ignores_non_data_lines() {
+        let mut buffer = b"event: ping\nid: 1\n\ndata: {\"choices\":[{\"delta\":{\"content\":\"x\"}}]}\n\n".to_vec();
+        let frames = drain_complete_frames(&mut buffer);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].delta_content(openai_format().as_ref()), Some("x".to_string()));
+    }
+
+    #[test]
+    fn test_is_done_and_delta_content_for_done_marker() {
+        let mut buffer = b"data: [DONE]\n\n".to_vec();
+        let frames = drain_complete_frames(&mut buffer);
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].is_done());
+        assert_eq!(frames[0].delta_content(openai_format().as_ref()), None);
+    }
+
+    #[test]
+    fn test_delta_content_missing_field_returns_none() {
+        let frame = SseFrame { raw_data: r#"{"choices":[{"delta":{}}]}"#.to_string() };
+        assert_eq!(frame.delta_content(openai_format().as_ref()), None);
+    }
+
+    #[test]
+    fn test_delta_content_routes_through_anthropic_format() {
+        let anthropic = formats::adapter_for(formats::Provider::Anthropic);
+        let frame = SseFrame {
+            raw_data: r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"hi"}}"#.to_string(),
+        };
+        assert_eq!(frame.delta_content(anthropic.as_ref()), Some("hi".to_string()));
+
+        // Non-text-delta events (message_start, ping, ...) carry no content.
+        let ping = SseFrame { raw_data: r#"{"type":"ping"}"#.to_string() };
+        assert_eq!(ping.delta_content(anthropic.as_ref()), None);
+    }
+
+    #[test]
+    fn test_delta_content_routes_through_gemini_format() {
+        let gemini = formats::adapter_for(formats::Provider::Gemini);
+        let frame = SseFrame {
+            raw_data: r#"{"candidates":[{"content":{"parts":[{"text":"hi"}]}}]}"#.to_string(),
+        };
+        assert_eq!(frame.delta_content(gemini.as_ref()), Some("hi".to_string()));
+    }
+
+    #[test]
+    fn test_render_content_event_includes_done_marker() {
+        let event = String::from_utf8(render_content_event("hello")).unwrap();
+        assert!(event.contains("\"content\":\"hello\""));
+        assert!(event.ends_with("data: [DONE]\n\n"));
+    }
+
+    #[test]
+    fn test_render_done_is_bare_marker() {
+        assert_eq!(render_done(), b"data: [DONE]\n\n".to_vec());
+    }
+
+    #[test]
+    fn test_render_block_event_sets_content_filter_and_done() {
+        let event = String::from_utf8(render_block_event("blocked")).unwrap();
+        assert!(event.contains("\"finish_reason\":\"content_filter\""));
+        assert!(event.contains("\"content\":\"blocked\""));
+        assert!(event.ends_with("data: [DONE]\n\n"));
+    }
+
+    #[test]
+    fn test_render_passthrough_forwards_frames_verbatim() {
+        let frames = vec![
+            PendingFrame::Text(r#"{"id":"c1","choices":[{"delta":{"content":"hi"}}]}"#.to_string()),
+            PendingFrame::Raw(r#"{"id":"c1","choices":[{"delta":{"tool_calls":[{"id":"t1"}]}}]}"#.to_string()),
+        ];
+        let out = String::from_utf8(render_passthrough(&frames, false)).unwrap();
+        assert!(out.contains("\"content\":\"hi\""));
+        assert!(out.contains("\"tool_calls\""));
+        assert!(!out.contains("[DONE]"));
+    }
+
+    #[test]
+    fn test_render_passthrough_appends_done_when_final() {
+        let frames = vec![PendingFrame::Text(r#"{"choices":[{"delta":{"content":"hi"}}]}"#.to_string())];
+        let out = String::from_utf8(render_passthrough(&frames, true)).unwrap();
+        assert!(out.ends_with("data: [DONE]\n\n"));
+    }
+
+    #[test]
+    fn test_render_rewritten_puts_replacement_on_last_text_frame_only() {
+        let openai = openai_format();
+        let frames = vec![
+            PendingFrame::Text(r#"{"id":"c1","choices":[{"delta":{"content":"he"}}]}"#.to_string()),
+            PendingFrame::Raw(r#"{"id":"c1","choices":[{"delta":{"tool_calls":[{"id":"t1"}]}}]}"#.to_string()),
+            PendingFrame::Text(r#"{"id":"c1","choices":[{"delta":{"content":"llo"}}]}"#.to_string()),
+        ];
+        let out = String::from_utf8(render_rewritten(&frames, "HELLO", openai.as_ref(), true)).unwrap();
+        // First text frame's content was cleared, not dropped - `id` survives.
+        assert!(out.contains(r#""id":"c1","choices":[{"delta":{"content":""}}]"#));
+        // The tool-call frame passed through untouched.
+        assert!(out.contains("\"tool_calls\":[{\"id\":\"t1\"}]"));
+        // The last text frame carries the full replacement.
+        assert!(out.contains(r#""content":"HELLO""#));
+        assert!(out.ends_with("data: [DONE]\n\n"));
+    }
+
+    #[test]
+    fn test_render_rewritten_falls_back_to_raw_on_invalid_json() {
+        let openai = openai_format();
+        let frames = vec![PendingFrame::Text("not json".to_string())];
+        let out = String::from_utf8(render_rewritten(&frames, "HELLO", openai.as_ref(), false)).unwrap();
+        assert_eq!(out, "data: not json\n\n");
+    }
+}
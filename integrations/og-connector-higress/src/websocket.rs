@@ -0,0 +1,311 @@
+/*!
+Minimal WebSocket (RFC 6455) frame parsing and building for the realtime/voice
+upgrade path.
+
+Once a request negotiates `Upgrade: websocket`, Envoy keeps delivering the
+tunneled bytes through the same `on_http_request_body`/`on_http_response_body`
+callbacks instead of ordinary HTTP body chunks. This module turns that raw
+byte stream into reassembled application messages (text frames split across
+continuation frames are joined into one `Message::Text`) and turns a decided
+message back into wire bytes - it doesn't know anything about detection or
+the og-connector's state machine, just the framing.
+*/
+
+use std::collections::VecDeque;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// Close code sent when a block/replace verdict terminates the connection.
+pub(crate) const CLOSE_POLICY_VIOLATION: u16 = 1008;
+
+/// One reassembled application message: a complete text/binary payload once
+/// all of its continuation frames have arrived, or a control frame (close/
+/// ping/pong), which the spec requires to fit in a single frame.
+#[derive(Debug, Clone)]
+pub(crate) enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Close(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// Parse one complete frame off the front of `buf`, returning it and the
+/// number of bytes it occupied. `None` if `buf` doesn't yet hold a full frame.
+fn parse_frame(buf: &[u8]) -> Option<(Frame, usize)> {
+    if buf.len() < 2 {
+        return None;
+    }
+    let fin = buf[0] & 0x80 != 0;
+    let opcode = buf[0] & 0x0f;
+    let masked = buf[1] & 0x80 != 0;
+    let mut len = (buf[1] & 0x7f) as u64;
+    let mut offset = 2;
+
+    if len == 126 {
+        if buf.len() < offset + 2 {
+            return None;
+        }
+        len = u16::from_be_bytes([buf[offset], buf[offset + 1]]) as u64;
+        offset += 2;
+    } else if len == 127 {
+        if buf.len() < offset + 8 {
+            return None;
+        }
+        let mut arr = [0u8; 8];
+        arr.copy_from_slice(&buf[offset..offset + 8]);
+        len = u64::from_be_bytes(arr);
+        offset += 8;
+    }
+
+    let mask_key = if masked {
+        if buf.len() < offset + 4 {
+            return None;
+        }
+        let key = [buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]];
+        offset += 4;
+        Some(key)
+    } else {
+        None
+    };
+
+    let len = len as usize;
+    if buf.len() < offset + len {
+        return None;
+    }
+
+    let mut payload = buf[offset..offset + len].to_vec();
+    if let Some(key) = mask_key {
+        for (i, b) in payload.iter_mut().enumerate() {
+            *b ^= key[i % 4];
+        }
+    }
+
+    Some((Frame { fin, opcode, payload }, offset + len))
+}
+
+fn finish_message(opcode: u8, data: Vec<u8>) -> Message {
+    if opcode == OPCODE_TEXT {
+        Message::Text(String::from_utf8_lossy(&data).to_string())
+    } else {
+        Message::Binary(data)
+    }
+}
+
+/// Drain every complete message from the front of `buf`, leaving any
+/// trailing partial frame for the next call. `fragment` carries an
+/// in-progress text/binary message (opcode + payload so far) across calls
+/// until a frame with `fin=true` closes it out.
+pub(crate) fn drain_messages(buf: &mut Vec<u8>, fragment: &mut Option<(u8, Vec<u8>)>) -> VecDeque<Message> {
+    let mut messages = VecDeque::new();
+    let mut consumed = 0;
+
+    while let Some((frame, frame_len)) = parse_frame(&buf[consumed..]) {
+        consumed += frame_len;
+
+        match frame.opcode {
+            OPCODE_CONTINUATION => {
+                if let Some((_, data)) = fragment.as_mut() {
+                    data.extend_from_slice(&frame.payload);
+                    if frame.fin {
+                        let (opcode, data) = fragment.take().unwrap();
+                        messages.push_back(finish_message(opcode, data));
+                    }
+                }
+            }
+            OPCODE_TEXT | OPCODE_BINARY => {
+                if frame.fin {
+                    messages.push_back(finish_message(frame.opcode, frame.payload));
+                } else {
+                    *fragment = Some((frame.opcode, frame.payload));
+                }
+            }
+            OPCODE_CLOSE => messages.push_back(Message::Close(frame.payload)),
+            OPCODE_PING => messages.push_back(Message::Ping(frame.payload)),
+            OPCODE_PONG => messages.push_back(Message::Pong(frame.payload)),
+            _ => {}
+        }
+    }
+
+    if consumed > 0 {
+        buf.drain(0..consumed);
+    }
+    messages
+}
+
+/// Build one frame. `mask_key` must be `Some` for every client->server frame
+/// (RFC 6455 SS5.1: "a server MUST close the connection upon receiving an
+/// unmasked frame") and `None` for server->client frames, which MUST NOT be
+/// masked.
+fn build_frame(opcode: u8, payload: &[u8], mask_key: Option<[u8; 4]>) -> Vec<u8> {
+    let mut out = vec![0x80 | opcode]; // FIN=1, always rebuilt as a single frame
+    let len = payload.len();
+    let mask_bit = if mask_key.is_some() { 0x80 } else { 0x00 };
+    if len < 126 {
+        out.push(mask_bit | len as u8);
+    } else if len <= u16::MAX as usize {
+        out.push(mask_bit | 126);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(mask_bit | 127);
+        out.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+    match mask_key {
+        Some(key) => {
+            out.extend_from_slice(&key);
+            out.extend(payload.iter().enumerate().map(|(i, b)| b ^ key[i % 4]));
+        }
+        None => out.extend_from_slice(payload),
+    }
+    out
+}
+
+/// Rebuild a message as a single frame, masked with `mask_key` for the
+/// client->server direction (`Some`, see [`build_frame`]) or unmasked for
+/// server->client (`None`). Reassembly already joined any continuation
+/// frames, so forwarded messages don't preserve the upstream's original
+/// fragmentation boundaries - acceptable since WebSocket framing is
+/// transparent to the application on either end.
+pub(crate) fn build_message_frame(message: &Message, mask_key: Option<[u8; 4]>) -> Vec<u8> {
+    match message {
+        Message::Text(text) => build_frame(OPCODE_TEXT, text.as_bytes(), mask_key),
+        Message::Binary(data) => build_frame(OPCODE_BINARY, data, mask_key),
+        Message::Close(data) => build_frame(OPCODE_CLOSE, data, mask_key),
+        Message::Ping(data) => build_frame(OPCODE_PING, data, mask_key),
+        Message::Pong(data) => build_frame(OPCODE_PONG, data, mask_key),
+    }
+}
+
+/// Build a standalone text frame for forwarding a detection-decided
+/// (original or anonymized) message back into the client->server direction,
+/// masked with `mask_key` as [`build_frame`] requires.
+pub(crate) fn build_text_frame(text: &str, mask_key: [u8; 4]) -> Vec<u8> {
+    build_frame(OPCODE_TEXT, text.as_bytes(), Some(mask_key))
+}
+
+/// Build an unmasked close frame carrying `code` and a short UTF-8 reason,
+/// sent to the client in place of the blocked message when a block/replace
+/// verdict ends the connection - this is the server->client direction, so
+/// per RFC 6455 it must not be masked.
+pub(crate) fn build_close_frame(code: u16, reason: &str) -> Vec<u8> {
+    let mut payload = code.to_be_bytes().to_vec();
+    payload.extend_from_slice(reason.as_bytes());
+    build_frame(OPCODE_CLOSE, &payload, None)
+}
+
+/// Derive a client->server masking key from a caller-supplied, per-frame
+/// varying seed. RFC 6455 requires masking primarily so misbehaving
+/// intermediaries can't be tricked by cache-poisoning-style attacks, not
+/// cryptographic unpredictability, so a cheap xorshift is enough here - the
+/// og-connector just needs a fresh-looking key per forwarded frame.
+pub(crate) fn next_mask_key(seed: u64) -> [u8; 4] {
+    let mut x = seed ^ 0x9E37_79B9_7F4A_7C15;
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    let bytes = x.to_le_bytes();
+    [bytes[0], bytes[1], bytes[2], bytes[3]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_then_parse_masked_text_frame_round_trips() {
+        let wire = build_text_frame("hello", [0x01, 0x02, 0x03, 0x04]);
+        // Masked client->server frames must have the mask bit set.
+        assert_eq!(wire[1] & 0x80, 0x80);
+
+        let (frame, consumed) = parse_frame(&wire).unwrap();
+        assert_eq!(consumed, wire.len());
+        assert_eq!(frame.opcode, OPCODE_TEXT);
+        assert_eq!(frame.payload, b"hello");
+    }
+
+    #[test]
+    fn test_build_message_frame_unmasked_for_server_to_client() {
+        let wire = build_message_frame(&Message::Text("hi".to_string()), None);
+        // Server->client frames must NOT have the mask bit set.
+        assert_eq!(wire[1] & 0x80, 0x00);
+        assert_eq!(&wire[2..], b"hi");
+    }
+
+    #[test]
+    fn test_build_close_frame_is_unmasked_and_carries_code_and_reason() {
+        let wire = build_close_frame(CLOSE_POLICY_VIOLATION, "blocked");
+        assert_eq!(wire[0] & 0x0f, OPCODE_CLOSE);
+        assert_eq!(wire[1] & 0x80, 0x00);
+
+        let (frame, _) = parse_frame(&wire).unwrap();
+        assert_eq!(u16::from_be_bytes([frame.payload[0], frame.payload[1]]), CLOSE_POLICY_VIOLATION);
+        assert_eq!(&frame.payload[2..], b"blocked");
+    }
+
+    #[test]
+    fn test_drain_messages_reassembles_continuation_frames() {
+        // One text message fragmented across a start frame (fin=0) and a
+        // continuation frame (fin=1), both unmasked as if from the server.
+        let mut buf = build_frame(OPCODE_TEXT, b"hel", None);
+        buf[0] &= !0x80; // clear FIN on the first frame
+        buf.extend(build_frame(OPCODE_CONTINUATION, b"lo", None));
+
+        let mut fragment = None;
+        let messages = drain_messages(&mut buf, &mut fragment);
+        assert_eq!(messages.len(), 1);
+        match &messages[0] {
+            Message::Text(text) => assert_eq!(text, "hello"),
+            other => panic!("expected Text, got {other:?}"),
+        }
+        assert!(buf.is_empty());
+        assert!(fragment.is_none());
+    }
+
+    #[test]
+    fn test_drain_messages_leaves_partial_frame_for_next_call() {
+        let full = build_frame(OPCODE_TEXT, b"hello", None);
+        let mut buf = full[..full.len() - 2].to_vec();
+
+        let mut fragment = None;
+        assert!(drain_messages(&mut buf, &mut fragment).is_empty());
+        assert_eq!(buf.len(), full.len() - 2);
+
+        buf.extend_from_slice(&full[full.len() - 2..]);
+        let messages = drain_messages(&mut buf, &mut fragment);
+        assert_eq!(messages.len(), 1);
+        match &messages[0] {
+            Message::Text(text) => assert_eq!(text, "hello"),
+            other => panic!("expected Text, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_long_payload_uses_16_bit_extended_length() {
+        let payload = vec![0x41u8; 200];
+        let wire = build_frame(OPCODE_BINARY, &payload, None);
+        assert_eq!(wire[1], 126);
+        assert_eq!(u16::from_be_bytes([wire[2], wire[3]]) as usize, 200);
+
+        let (frame, consumed) = parse_frame(&wire).unwrap();
+        assert_eq!(consumed, wire.len());
+        assert_eq!(frame.payload, payload);
+    }
+
+    #[test]
+    fn test_next_mask_key_varies_with_seed() {
+        let a = next_mask_key(1);
+        let b = next_mask_key(2);
+        assert_ne!(a, b);
+    }
+}